@@ -0,0 +1,295 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use ree_exchange_sdk::types::bitcoin::hashes::{sha256, Hash};
+
+use crate::{
+    errors::Result,
+    exchange::exchange::{ChallengeSalt, ClaimWindowStarts, ClaimWindows, __CustomStorageAccess},
+    game::{game::Game, gamer::Gamer},
+    *,
+};
+
+/// The window `AntiBotSettings::max_claims_per_minute` counts the claims of a principal in.
+pub const CLAIM_RATE_WINDOW: Seconds = 60;
+/// At most this many expired windows are evicted whenever a new window starts.
+const MAX_WINDOW_EVICTIONS: usize = 16;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ClaimViolation {
+    /// The principal claimed too often across all games, it may claim again after the timestamp.
+    RateLimited(SecondTimestamp),
+    /// The game requires the nonce from `get_claim_challenge`, but the claim came without one.
+    ChallengeMissing,
+    ChallengeMismatch,
+}
+
+/// The claims of a principal in the current rate limit window, across all games.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ClaimWindow {
+    pub window_start: SecondTimestamp,
+    pub claims: u32,
+}
+
+impl Storable for ClaimWindow {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(bincode::serialize(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bincode::serialize_into(&mut bytes, &self).unwrap();
+        bytes
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl ClaimWindow {
+    fn current(&self, now: SecondTimestamp) -> Self {
+        if now >= self.window_start.saturating_add(CLAIM_RATE_WINDOW) {
+            Self {
+                window_start: now,
+                claims: 0,
+            }
+        } else {
+            self.clone()
+        }
+    }
+}
+
+/// Fills the salt of the claim challenges with randomness once, must be called on init and
+/// post_upgrade. Until it is filled, games requiring a challenge can't be claimed.
+pub fn ensure_challenge_salt() {
+    if ChallengeSalt::with(|c| c.get().is_some()) {
+        return;
+    }
+    ic_cdk_timers::set_timer(Duration::ZERO, || {
+        ic_cdk::futures::spawn(async {
+            match ic_cdk::management_canister::raw_rand().await {
+                Ok(salt) => ChallengeSalt::with_mut(|c| {
+                    c.set(Some(salt));
+                }),
+                Err(e) => log!(
+                    ERROR,
+                    "Failed to get randomness for claim challenges: {:?}",
+                    e
+                ),
+            }
+        })
+    });
+}
+
+fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut bytes = vec![];
+    for part in parts {
+        bytes.extend_from_slice(part);
+    }
+    sha256::Hash::hash(&bytes).to_byte_array()
+}
+
+/// The nonce a gamer has to echo with its next claim. It changes with every claim, so
+/// each claim needs a fresh `get_claim_challenge` call.
+pub fn claim_challenge(game: &Game, principal: &Principal, gamer: &Gamer) -> Result<u64> {
+    let salt = ChallengeSalt::with(|c| c.get().clone()).ok_or(ExchangeError::CustomError(
+        "Claim challenges not ready".to_string(),
+    ))?;
+    let digest = hash(&[
+        &salt,
+        principal.as_slice(),
+        game.game_id.as_bytes(),
+        &gamer.last_click_time.to_be_bytes(),
+    ]);
+    Ok(u64::from_be_bytes(digest[..8].try_into().unwrap()))
+}
+
+/// An extra cooldown between 0 and `max_jitter` seconds after the last claim of `gamer`. It is
+/// derived from the salt, so a client can't know it in advance. Until the salt is filled,
+/// the whole `max_jitter` applies.
+pub fn cooldown_jitter(max_jitter: Seconds, game: &Game, gamer: &Gamer) -> Seconds {
+    if max_jitter == 0 {
+        return 0;
+    }
+    let Some(salt) = ChallengeSalt::with(|c| c.get().clone()) else {
+        return max_jitter;
+    };
+    let digest = hash(&[
+        &salt,
+        b"cooldown",
+        game.game_id.as_bytes(),
+        gamer.address.as_bytes(),
+        &gamer.last_click_time.to_be_bytes(),
+    ]);
+    u64::from_be_bytes(digest[..8].try_into().unwrap()) % (max_jitter + 1)
+}
+
+/// Checks the rate limit and challenge settings of `game` before `principal` claims in it.
+pub fn check_claim(
+    game: &Game,
+    principal: &Principal,
+    gamer: &Gamer,
+    challenge: Option<u64>,
+    now: SecondTimestamp,
+) -> Result<()> {
+    if let Some(max_claims) = game.anti_bot.max_claims_per_minute {
+        let window = ClaimWindows::with(|m| m.get(principal))
+            .unwrap_or_default()
+            .current(now);
+        if window.claims >= max_claims {
+            return Err(ExchangeError::ClaimRejected(ClaimViolation::RateLimited(
+                window.window_start.saturating_add(CLAIM_RATE_WINDOW),
+            )));
+        }
+    }
+
+    if game.anti_bot.require_challenge {
+        let challenge = challenge.ok_or(ExchangeError::ClaimRejected(
+            ClaimViolation::ChallengeMissing,
+        ))?;
+        if challenge != claim_challenge(game, principal, gamer)? {
+            return Err(ExchangeError::ClaimRejected(
+                ClaimViolation::ChallengeMismatch,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Counts a successful claim of `principal` towards its rate limit, in whatever game.
+pub fn record_claim(principal: &Principal, now: SecondTimestamp) {
+    let old_window = ClaimWindows::with(|m| m.get(principal));
+    let mut window = old_window.clone().unwrap_or_default().current(now);
+    window.claims = window.claims.saturating_add(1);
+
+    let is_new_window = old_window
+        .as_ref()
+        .map_or(true, |old| old.window_start != window.window_start);
+    if is_new_window {
+        ClaimWindowStarts::with_mut(|m| {
+            if let Some(old) = &old_window {
+                m.remove(&(old.window_start, *principal));
+            }
+            m.insert((window.window_start, *principal), ());
+        });
+        evict_expired_windows(now);
+    }
+    ClaimWindows::with_mut(|m| {
+        m.insert(*principal, window);
+    });
+}
+
+/// Drops the oldest windows that can no longer limit any claim, so that principals who
+/// stopped claiming don't keep their window forever.
+fn evict_expired_windows(now: SecondTimestamp) {
+    let expired = ClaimWindowStarts::with(|m| {
+        m.iter()
+            .map(|e| e.key().clone())
+            .take_while(|(window_start, _)| window_start.saturating_add(CLAIM_RATE_WINDOW) <= now)
+            .take(MAX_WINDOW_EVICTIONS)
+            .collect::<Vec<_>>()
+    });
+    for key in expired {
+        ClaimWindowStarts::with_mut(|m| m.remove(&key));
+        ClaimWindows::with_mut(|m| m.remove(&key.1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::game::{AntiBotSettings, CreateGameArgs};
+
+    fn game(game_id: &str, anti_bot: AntiBotSettings) -> Game {
+        Game::new(
+            CreateGameArgs {
+                game_name: "anti bot".to_string(),
+                gamer_register_fee: DUST_BTC_VALUE,
+                claim_cooling_down: 10,
+                claim_amount_per_click: 10,
+                create_address: "creator".to_string(),
+                rune_premine_amount: 1000,
+                anti_bot: Some(anti_bot),
+                ..Default::default()
+            },
+            Principal::anonymous(),
+            game_id.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_rate_limit_counts_claims_across_games() {
+        let anti_bot = AntiBotSettings {
+            max_claims_per_minute: Some(2),
+            ..Default::default()
+        };
+        let first = game("1", anti_bot.clone());
+        let second = game("2", anti_bot);
+        let gamer = Gamer::new("alice".to_string());
+        let principal = Principal::anonymous();
+
+        // one claim in each game uses up the limit in both
+        assert!(check_claim(&first, &principal, &gamer, None, 100).is_ok());
+        record_claim(&principal, 100);
+        assert!(check_claim(&second, &principal, &gamer, None, 110).is_ok());
+        record_claim(&principal, 110);
+        for game in [&first, &second] {
+            assert!(matches!(
+                check_claim(game, &principal, &gamer, None, 120),
+                Err(ExchangeError::ClaimRejected(ClaimViolation::RateLimited(
+                    160
+                )))
+            ));
+        }
+        assert!(check_claim(&first, &principal, &gamer, None, 160).is_ok());
+    }
+
+    #[test]
+    fn test_new_windows_evict_expired_ones() {
+        let idle = Principal::management_canister();
+        let active = Principal::anonymous();
+
+        record_claim(&idle, 100);
+        record_claim(&active, 150);
+        assert!(ClaimWindows::with(|m| m.contains_key(&idle)));
+
+        record_claim(&active, 210);
+        assert!(!ClaimWindows::with(|m| m.contains_key(&idle)));
+        assert_eq!(ClaimWindows::with(|m| m.get(&active)).unwrap().claims, 1);
+        assert_eq!(ClaimWindowStarts::with(|m| m.len()), 1);
+    }
+
+    #[test]
+    fn test_challenge_must_match_the_last_claim() {
+        ChallengeSalt::with_mut(|c| {
+            c.set(Some(vec![7; 32]));
+        });
+        let game = game(
+            "1",
+            AntiBotSettings {
+                require_challenge: true,
+                ..Default::default()
+            },
+        );
+        let mut gamer = Gamer::new("alice".to_string());
+        let principal = Principal::anonymous();
+
+        let challenge = claim_challenge(&game, &principal, &gamer).unwrap();
+        assert!(check_claim(&game, &principal, &gamer, Some(challenge), 0).is_ok());
+        assert!(matches!(
+            check_claim(&game, &principal, &gamer, None, 0),
+            Err(ExchangeError::ClaimRejected(ClaimViolation::ChallengeMissing))
+        ));
+
+        gamer.last_click_time = 100;
+        assert!(matches!(
+            check_claim(&game, &principal, &gamer, Some(challenge), 0),
+            Err(ExchangeError::ClaimRejected(ClaimViolation::ChallengeMismatch))
+        ));
+    }
+}
@@ -1,67 +1,144 @@
-use std::str::FromStr;
-
 pub use crate::log::*;
 use crate::{
+    anti_bot::{self, ensure_challenge_salt},
+    config::{self, mutate_config, read_config, CanisterConfig, CookieArgs, UpgradeArgs},
+    deadline::{self, schedule_deadline_checks},
+    etching::{
+        self, finalize_etching, schedule_etch_check, schedule_etching_checks,
+        unschedule_etch_check, EtchingProgress,
+    },
     exchange::{
         self,
         exchange::{
-            AddressPrincipalMap, CookiePools, State, __CustomStorageAccess, mutate_state,
-            read_state, ReceiverRunesMap,
+            games_of, get_game, insert_game, migrate_legacy_games, mutate_game, next_game_id,
+            rebuild_game_name_index, rebuild_game_order_index, rebuild_gamer_games_index,
+            rebuild_pool_address_index,
+            AddressPrincipalMap, CookiePools, GameNames, Games, State, __CustomStorageAccess,
+            ReceiverRunesMap,
         },
         CookiePoolState,
     },
     external::{
-        etch_canister::{etching, get_etching_request, EtchingArgs, EtchingStatus},
+        etch_canister::{etching, EtchingArgs},
         internal_identity::get_principal,
-        rune_indexer::get_etching,
     },
-    game::game::{CreateGameArgs, Game, GameAndPool, RuneInfo},
+    game::{
+        game::{
+            CreateGameArgs, Game, GameAndGamer, GameAndPool, GameStatus, GameSummary,
+            ListGamesArgs, RuneInfo, MAX_PAGE_SIZE,
+        },
+        gamer::Gamer,
+        leaderboard::{self, GamerStats, LeaderboardEntry},
+        referral::{self, Referral},
+    },
     log,
     state::ExchangeState,
-    utils::AddLiquidityInfo,
-    AddressStr, ExchangeError, GameId, DUST_BTC_VALUE,
+    utils::{get_chain_second_timestamp, AddLiquidityInfo},
+    AddressStr, ExchangeError, GameId, Principal, SecondTimestamp,
 };
 use ic_cdk::{init, post_upgrade, query, update};
 use ic_stable_structures::Storable;
 use itertools::Itertools;
 use ree_exchange_sdk::{
     prelude::schnorr::request_p2tr_address,
-    types::{CoinBalance, CoinBalances, CoinId, Txid, Utxo},
+    types::CoinId,
     Metadata, PoolStorageAccess,
 };
 
 #[init]
-fn init() {
+fn init(args: CookieArgs) {
+    let CookieArgs::Init(init_args) = args else {
+        ic_cdk::trap("Cannot initialize the canister with upgrade args");
+    };
     State::with_mut(|s| {
         s.set(Some(ExchangeState::default()));
     });
+    mutate_config(|c| *c = CanisterConfig::from(init_args));
+    schedule_etching_checks();
+    schedule_deadline_checks();
+    ensure_challenge_salt();
+}
+
+/// The caller of an update, resolved once so that the permission checks run in tests.
+struct Caller {
+    principal: Principal,
+    is_controller: bool,
+}
+
+impl Caller {
+    fn current() -> Self {
+        let principal = ic_cdk::caller();
+        Self {
+            is_controller: ic_cdk::api::is_controller(&principal),
+            principal,
+        }
+    }
+
+    fn ensure_controller(&self) -> Result<(), String> {
+        if self.is_controller {
+            Ok(())
+        } else {
+            Err("Only controllers can call this method".to_string())
+        }
+    }
+
+    fn ensure_game_creator(&self, game: &Game) -> Result<(), String> {
+        if game.creator == self.principal {
+            Ok(())
+        } else {
+            Err("Only game creator can manage the game".to_string())
+        }
+    }
+
+    fn ensure_game_creator_or_controller(&self, game: &Game) -> Result<(), String> {
+        self.ensure_game_creator(game)
+            .or_else(|_| self.ensure_controller())
+    }
+}
+
+#[query]
+fn get_config() -> CanisterConfig {
+    read_config(|c| c.clone())
 }
 
 #[update]
-pub async fn create_game(create_game_args: CreateGameArgs) -> Result<GameId, String> {
+fn update_config(args: UpgradeArgs) -> Result<(), String> {
+    change_config(&Caller::current(), args)
+}
+
+fn change_config(caller: &Caller, args: UpgradeArgs) -> Result<(), String> {
+    caller.ensure_controller()?;
+    mutate_config(|c| c.update(args));
+    Ok(())
+}
+
+#[update]
+pub async fn create_game(mut create_game_args: CreateGameArgs) -> Result<GameId, String> {
+    // the trimmed name is the one checked, stored and indexed
+    create_game_args.game_name = create_game_args.game_name.trim().to_string();
+    create_game_args
+        .validate(get_chain_second_timestamp())
+        .map_err(|e| e.to_string())?;
+
     let principle = get_principal(create_game_args.create_address.to_string()).await?;
-    assert_eq!(ic_cdk::caller().to_text(), principle.to_text());
+    if ic_cdk::caller() != principle {
+        return Err(
+            ExchangeError::CallerNotAddressOwner(create_game_args.create_address.clone())
+                .to_string(),
+        );
+    }
 
-    assert!(
-        create_game_args.rune_premine_amount > create_game_args.claim_amount_per_click,
-        "Total cookie amount must be greater than cookie amount per claim"
-    );
-    assert!(
-        create_game_args.rune_premine_amount % 10 == 0,
-        "Total cookie amount must be divisible by 10"
-    );
-    assert!(
-        (create_game_args.rune_premine_amount * 4 / 5) % create_game_args.claim_amount_per_click
-            == 0,
-        "Total cookie amount must be divisible by cookie amount per claim"
-    );
+    // checked after the await, another game may have taken the name meanwhile
+    let game_name = create_game_args.game_name.clone();
+    if GameNames::with(|m| m.contains_key(&game_name)) {
+        return Err(ExchangeError::GameNameAlreadyExist(game_name).to_string());
+    }
 
-    let game_id = mutate_state(|s| {
-        let game_id = s.games.len().to_string();
-        let game = Game::new(create_game_args, ic_cdk::caller(), game_id.clone());
-        s.games.insert(game_id.clone(), game);
-        game_id
-    });
+    let game_id = next_game_id();
+    let game = Game::new(create_game_args, ic_cdk::caller(), game_id.clone());
+    deadline::schedule_game_end(&game);
+    GameNames::with_mut(|m| m.insert(game_name, game_id.clone()));
+    insert_game(game);
 
     Ok(game_id)
 }
@@ -69,7 +146,7 @@ pub async fn create_game(create_game_args: CreateGameArgs) -> Result<GameId, Str
 #[update]
 pub async fn get_game_pool_address(game_id: GameId) -> AddressStr {
     let pool_opt = exchange::exchange::CookiePools::get(&game_id);
-    let game = read_state(|es| es.games.get(&game_id).expect("Game Not Found").clone());
+    let game = get_game(&game_id).expect("Game Not Found");
 
     if let Some(pool) = pool_opt {
         return pool.metadata().address.to_string();
@@ -80,7 +157,7 @@ pub async fn get_game_pool_address(game_id: GameId) -> AddressStr {
         //     .expect("Failed to request address");
         let (_pubkey, _tweaked_pubkey, pool_address) = request_p2tr_address(
             vec![key_path.to_bytes().to_vec()],
-            ree_exchange_sdk::Network::Testnet4,
+            config::network().into(),
         )
         .await
         .expect("Failed to request address");
@@ -88,14 +165,16 @@ pub async fn get_game_pool_address(game_id: GameId) -> AddressStr {
     }
 }
 
-#[query]
-fn get_exchange_state() -> ExchangeState {
-    read_state(|s| s.clone())
-}
-
+/// Deprecated, returns at most `MAX_PAGE_SIZE` games, use `list_games` and `get_game_info`
+/// instead.
 #[query]
 fn get_games_info() -> Vec<GameAndPool> {
-    let games = read_state(|es| es.games.values().cloned().collect_vec());
+    let games = Games::with(|m| {
+        m.iter()
+            .take(MAX_PAGE_SIZE as usize)
+            .map(|e| e.value())
+            .collect_vec()
+    });
 
     let mut game_and_pool_list = vec![];
     for game in games {
@@ -121,9 +200,20 @@ fn get_games_info() -> Vec<GameAndPool> {
     game_and_pool_list
 }
 
+#[query]
+fn list_games(args: ListGamesArgs) -> Vec<GameSummary> {
+    exchange::exchange::list_games(&args)
+}
+
+#[query]
+fn list_gamers(game_id: GameId, offset: u64, limit: u64) -> Result<Vec<Gamer>, String> {
+    let game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
+    Ok(game.gamers_page(offset, limit))
+}
+
 #[query]
 fn get_game_info(game_id: GameId) -> Option<GameAndPool> {
-    let game_opt = read_state(|s| s.games.get(&game_id).cloned());
+    let game_opt = get_game(&game_id);
     if game_opt.is_none() {
         return None;
     }
@@ -143,36 +233,150 @@ fn get_game_info(game_id: GameId) -> Option<GameAndPool> {
     })
 }
 
+#[query]
+fn get_gamer_info(game_id: GameId, address: AddressStr) -> Result<GameAndGamer, String> {
+    let game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
+    game.game_and_gamer(game.get_gamer(&address), get_chain_second_timestamp())
+        .map_err(|e| e.to_string())
+}
+
+/// Every game the caller registered in, empty until the caller registers in any game.
+#[query]
+fn get_my_games() -> Result<Vec<GameAndGamer>, String> {
+    let now = get_chain_second_timestamp();
+    games_of(&ic_cdk::caller())
+        .into_iter()
+        .map(|(game, gamer)| game.game_and_gamer(Some(gamer), now))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[query]
+fn get_leaderboard(game_id: GameId, top_n: u64) -> Result<Vec<LeaderboardEntry>, String> {
+    if get_game(&game_id).is_none() {
+        return Err(ExchangeError::GameNotFound(game_id).to_string());
+    }
+    Ok(leaderboard::game_leaderboard(
+        &game_id,
+        top_n.min(MAX_PAGE_SIZE),
+    ))
+}
+
+#[query]
+fn get_global_leaderboard(top_n: u64) -> Vec<GamerStats> {
+    leaderboard::global_leaderboard(top_n.min(MAX_PAGE_SIZE))
+}
+
+/// The pool of the game a register should be sent to, see `Game::route_register_pool`.
+/// Withdrawals always go to `pool_address`, refunds to the shard the gamer registered in.
+#[query]
+fn route_register_pool(game_id: GameId) -> Result<AddressStr, String> {
+    let game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
+    game.route_register_pool().map_err(|e| e.to_string())
+}
+
+#[query]
+fn get_referrals(game_id: GameId, offset: u64, limit: u64) -> Result<Vec<Referral>, String> {
+    if get_game(&game_id).is_none() {
+        return Err(ExchangeError::GameNotFound(game_id).to_string());
+    }
+    Ok(referral::referrals_page(&game_id, offset, limit))
+}
+
 #[update]
-pub fn claim(game_id: GameId) -> Result<u128, ExchangeError> {
+pub fn claim(game_id: GameId, challenge: Option<u64>) -> Result<u128, ExchangeError> {
     let principal = ic_cdk::caller();
+    let address = caller_address(&principal)?;
+    claim_in_game(
+        &principal,
+        &address,
+        &game_id,
+        challenge,
+        get_chain_second_timestamp(),
+    )
+}
 
-    let address = AddressPrincipalMap::with(|m| {
-        m.get(&principal)
-            .ok_or(ExchangeError::GamerNotFound(principal.to_text().clone()))
-    })?;
+/// Claims once in each of at most `MAX_PAGE_SIZE` games, repeated ids are claimed once.
+/// Each claim succeeds or fails on its own, games requiring a claim challenge can't be
+/// claimed in a batch.
+#[update]
+pub fn claim_many(
+    game_ids: Vec<GameId>,
+) -> Result<Vec<(GameId, Result<u128, ExchangeError>)>, ExchangeError> {
+    let principal = ic_cdk::caller();
+    let address = caller_address(&principal)?;
+    claim_games(&principal, &address, game_ids, get_chain_second_timestamp())
+}
 
-    mutate_state(|s| {
-        s.games
-            .get_mut(&game_id)
-            .ok_or(ExchangeError::GameNotFound(game_id.clone()))?
-            .claim(address)
+fn claim_games(
+    principal: &Principal,
+    address: &AddressStr,
+    game_ids: Vec<GameId>,
+    now: SecondTimestamp,
+) -> Result<Vec<(GameId, Result<u128, ExchangeError>)>, ExchangeError> {
+    if game_ids.len() as u64 > MAX_PAGE_SIZE {
+        return Err(ExchangeError::TooManyGames(MAX_PAGE_SIZE));
+    }
+    Ok(game_ids
+        .into_iter()
+        .unique()
+        .map(|game_id| {
+            let result = claim_in_game(principal, address, &game_id, None, now);
+            (game_id, result)
+        })
+        .collect())
+}
+
+fn caller_address(principal: &Principal) -> Result<AddressStr, ExchangeError> {
+    AddressPrincipalMap::with(|m| {
+        m.get(principal)
+            .ok_or(ExchangeError::GamerNotFound(principal.to_text().clone()))
     })
 }
 
+fn claim_in_game(
+    principal: &Principal,
+    address: &AddressStr,
+    game_id: &GameId,
+    challenge: Option<u64>,
+    now: SecondTimestamp,
+) -> Result<u128, ExchangeError> {
+    if mutate_game(game_id, |game| game.end_if_expired(now))? {
+        return Err(ExchangeError::GameEnd);
+    }
+
+    let game = get_game(game_id).ok_or(ExchangeError::GameNotFound(game_id.clone()))?;
+    let gamer = game
+        .get_gamer(address)
+        .ok_or(ExchangeError::GamerNotFound(address.clone()))?;
+    anti_bot::check_claim(&game, principal, &gamer, challenge, now)?;
+
+    let cookies = mutate_game(game_id, |game| game.claim(address.clone(), now))?;
+    anti_bot::record_claim(principal, now);
+    Ok(cookies)
+}
+
+/// The nonce the caller has to pass to its next `claim` in games requiring a challenge.
+#[query]
+fn get_claim_challenge(game_id: GameId) -> Result<u64, ExchangeError> {
+    let principal = ic_cdk::caller();
+    let address = AddressPrincipalMap::with(|m| m.get(&principal))
+        .ok_or(ExchangeError::GamerNotFound(principal.to_text()))?;
+    let game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id))?;
+    let gamer = game
+        .get_gamer(&address)
+        .ok_or(ExchangeError::GamerNotFound(address))?;
+    anti_bot::claim_challenge(&game, &principal, &gamer)
+}
+
 #[update]
 async fn game_address(game_id: GameId) -> Result<String, String> {
-    let game = read_state(|s| {
-        s.games
-            .get(&game_id)
-            .cloned()
-            .ok_or_else(|| format!("Game with ID {} not found", game_id))
-    })?;
+    let game = get_game(&game_id).ok_or_else(|| format!("Game with ID {} not found", game_id))?;
     let key_path = game.key_path();
 
     let (_pubkey, _tweaked_pubkey, pool_address) = request_p2tr_address(
         vec![key_path.to_bytes().to_vec()],
-        ree_exchange_sdk::Network::Testnet4,
+        config::network().into(),
     )
     .await
     .expect("Failed to request address");
@@ -200,28 +404,14 @@ pub async fn query_etching_list(receiver: AddressStr) -> Vec<String> {
     ReceiverRunesMap::with(|m| m.get(&receiver).unwrap_or_default().commit_txs)
 }
 
-#[update]
-async fn etch_rune(game_id: GameId, rune_name: String) -> std::result::Result<String, String> {
-    let game = read_state(|s| {
-        s.games
-            .get(&game_id)
-            .cloned()
-            .ok_or_else(|| format!("Game with ID {} not found", game_id))
-    })?;
-
-    assert_eq!(
-        game.creator.to_text(),
-        ic_cdk::caller().to_text(),
-        "Only game creator can etch rune for game"
-    );
-
+async fn etch_game_rune(game: &Game, rune_name: String) -> Result<String, String> {
     let key_path = game.key_path();
     let (_pubkey, _tweaked_pubkey, pool_address) = request_p2tr_address(
         vec![key_path.to_bytes().to_vec()],
-        ree_exchange_sdk::Network::Testnet4,
+        config::network().into(),
     )
     .await
-    .expect("Failed to request address");
+    .map_err(|e| format!("Failed to request address: {:?}", e))?;
 
     let premine_amount = game.premine_rune_amount();
     let args = EtchingArgs {
@@ -238,107 +428,128 @@ async fn etch_rune(game_id: GameId, rune_name: String) -> std::result::Result<St
     let commit_tx = etching(args)
         .await
         .map_err(|e| format!("Failed to etch rune: {}", e))?;
-    mutate_state(|es| {
-        let game = es.games.get_mut(&game_id).expect("Game not found");
+    mutate_game(&game.game_id, |game| {
         game.rune_info = Some(RuneInfo {
             rune_id: CoinId::btc(), // Placeholder, should be set to the actual rune ID after etching
             rune_name,
             // rune_premine_amount: premine_amount,
         });
+        unschedule_etch_check(game);
         game.etch_rune_commit_tx = commit_tx.clone();
-    });
+        game.clear_etch_checks();
+        schedule_etch_check(game);
+        Ok(())
+    })
+    .map_err(|e| e.to_string())?;
 
     Ok(commit_tx)
 }
 
 #[update]
-pub async fn finalize_etch(game_id: GameId) -> Result<String, String> {
-    // query reveal tx id from etching canister
-    let game = read_state(|s| {
-        s.games
-            .get(&game_id)
-            .cloned()
-            .ok_or_else(|| format!("Game with ID {} not found", game_id))
-    })?;
-    let commit_tx = game.etch_rune_commit_tx.clone();
-
-    let info = get_etching_request(commit_tx)
-        .await
-        .ok_or("Failed to get etching request".to_string())?;
-
-    ic_cdk::println!("Etching request info: {:?}", info);
-
-    assert_eq!(
-        info.status,
-        EtchingStatus::Final,
-        "Etching not finalized yet"
-    );
-
-    let reveal_tx_id = info.reveal_txid;
+async fn etch_rune(game_id: GameId, rune_name: String) -> std::result::Result<String, String> {
+    let game = get_game(&game_id).ok_or_else(|| format!("Game with ID {} not found", game_id))?;
+    Caller::current().ensure_game_creator(&game)?;
 
-    let result = get_etching(reveal_tx_id.clone())
-        .await
-        .ok_or("Failed to get etching result".to_string())?;
+    etch_game_rune(&game, rune_name).await
+}
 
-    ic_cdk::println!("Etching result: {:?}", result,);
+/// Etches the rune of a game again after the etching canister reported a failure.
+#[update]
+async fn retry_etch_rune(game_id: GameId, rune_name: String) -> Result<String, String> {
+    let game = restart_etching(&Caller::current(), &game_id)?;
+    match etch_game_rune(&game, rune_name).await {
+        Ok(commit_tx) => Ok(commit_tx),
+        Err(e) => {
+            let _ = mutate_game(&game_id, |game| {
+                game.fail_etching(e.clone())?;
+                unschedule_etch_check(game);
+                Ok(())
+            });
+            Err(e)
+        }
+    }
+}
 
-    assert!(result.confirmations >= 1, "Etching not confirmed yet");
+/// Moves a failed game back to `Etching` before awaiting, so that concurrent retries are rejected.
+fn restart_etching(caller: &Caller, game_id: &GameId) -> Result<Game, String> {
+    let game = get_game(game_id).ok_or_else(|| format!("Game with ID {} not found", game_id))?;
+    caller.ensure_game_creator(&game)?;
 
-    let key_path = game.key_path();
-    let (pubkey, _tweaked_pubkey, pool_address) = request_p2tr_address(
-        vec![key_path.to_bytes().to_vec()],
-        ree_exchange_sdk::Network::Testnet4,
-    )
-    .await
-    .expect("Failed to request address");
+    mutate_game(game_id, |game| {
+        game.game_status = game.game_status.retry_etching()?;
+        game.etch_rune_commit_tx = "".to_string();
+        game.clear_etch_checks();
+        Ok(game.clone())
+    })
+    .map_err(|e| e.to_string())
+}
 
-    let mut coin_balances = CoinBalances::new();
-    coin_balances.add_coin(&CoinBalance {
-        id: CoinId::btc(),
-        value: DUST_BTC_VALUE as u128,
-    });
-    coin_balances.add_coin(&CoinBalance {
-        id: CoinId::from_str(result.rune_id.as_str())
-            .expect("Failed to parse rune ID from etching result"),
-        value: info.etching_args.premine.expect("Premine amount not found") as u128,
-    });
+fn update_game_status(
+    caller: &Caller,
+    game_id: GameId,
+    transition: impl FnOnce(&GameStatus) -> crate::errors::Result<GameStatus>,
+) -> Result<(), String> {
+    let game = get_game(&game_id).ok_or_else(|| format!("Game with ID {} not found", game_id))?;
+    caller.ensure_game_creator_or_controller(&game)?;
+
+    mutate_game(&game_id, |game| {
+        game.game_status = transition(&game.game_status)?;
+        Ok(())
+    })
+    .map_err(|e| e.to_string())
+}
 
-    exchange::exchange::new_pool_by_utxo(
-        key_path.clone(),
-        key_path,
-        pubkey,
-        pool_address.to_string(),
-        Utxo {
-            txid: Txid::from_str(&reveal_tx_id).expect("Failed to parse reveal tx id"),
-            vout: 1,
-            coins: coin_balances,
-            sats: DUST_BTC_VALUE,
-        },
-    );
+/// Stops claims and registrations until the game is resumed.
+#[update]
+fn pause_game(game_id: GameId) -> Result<(), String> {
+    update_game_status(&Caller::current(), game_id, GameStatus::pause)
+}
 
-    mutate_state(|es| {
-        let game = es.games.get_mut(&game_id).expect("Game not found");
-        let mut old_rune_info = game.rune_info.clone().expect("Rune info not found");
-        old_rune_info.rune_id = CoinId::from_str(result.rune_id.as_str())
-            .expect("Failed to parse rune ID from etching result");
-        game.rune_info = Some(old_rune_info);
-        game.pool_address = Some(pool_address.to_string());
+#[update]
+fn resume_game(game_id: GameId) -> Result<(), String> {
+    update_game_status(&Caller::current(), game_id, GameStatus::resume)
+}
 
-        game.game_status = game.game_status.finish_etching();
-    });
+/// Stops the game for good, registered gamers can then refund their register fee.
+#[update]
+fn cancel_game(game_id: GameId) -> Result<(), String> {
+    update_game_status(&Caller::current(), game_id, GameStatus::cancel)
+}
 
-    Ok(reveal_tx_id)
+/// Finalizes the etching right away instead of waiting for the background check.
+#[update]
+pub async fn finalize_etch(game_id: GameId) -> Result<String, String> {
+    match finalize_etching(game_id).await? {
+        EtchingProgress::Finalized(reveal_tx_id) => Ok(reveal_tx_id),
+        EtchingProgress::Pending(reason) => Err(reason),
+        EtchingProgress::Failed(err_info) => Err(format!("Etching failed: {}", err_info)),
+    }
 }
 
 #[query]
-pub fn query_add_liquidity_info(game_id: GameId) -> AddLiquidityInfo {
-    read_state(|s| {
-        let game = s.games.get(&game_id).expect("Game not found");
-
-        AddLiquidityInfo {
-            btc_amount_for_add_liquidity: game.gamer_register_fee * game.gamers.len() as u64,
-            rune_amount_for_add_liquidity: game.calculate_add_liquidity_rune_amount(),
-        }
+pub fn query_add_liquidity_info(
+    game_id: GameId,
+    shard: Option<u8>,
+) -> Result<AddLiquidityInfo, String> {
+    let mut game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
+    game.end_if_expired(get_chain_second_timestamp())
+        .map_err(|e| e.to_string())?;
+
+    // only shard 0 holds the runes, the fee pools add their register fees alone
+    let shard = shard.unwrap_or_default();
+    let rune_amount_for_add_liquidity = if shard == 0 {
+        game.calculate_add_liquidity_rune_amount()
+            .map_err(|e| e.to_string())?
+    } else {
+        0
+    };
+    Ok(AddLiquidityInfo {
+        btc_amount_for_add_liquidity: game
+            .shard_register_fees(shard)
+            .map_err(|e| e.to_string())?
+            .try_into()
+            .map_err(|_| ExchangeError::Overflow.to_string())?,
+        rune_amount_for_add_liquidity,
     })
 }
 
@@ -355,7 +566,55 @@ fn http_request(req: ic_http_types::HttpRequest) -> ic_http_types::HttpResponse
 }
 
 #[post_upgrade]
-fn post_upgrade() {
+fn post_upgrade(args: Option<CookieArgs>) {
+    match args {
+        Some(CookieArgs::Upgrade(Some(upgrade_args))) => mutate_config(|c| c.update(upgrade_args)),
+        Some(CookieArgs::Init(_)) => ic_cdk::trap("Cannot upgrade the canister with init args"),
+        _ => {}
+    }
+    schedule_etching_checks();
+    schedule_deadline_checks();
+    ensure_challenge_salt();
+
+    let migrated = migrate_legacy_games(get_chain_second_timestamp());
+    if migrated > 0 {
+        log!(INFO, "Migrated {} games out of the state cell", migrated);
+    }
+
+    let pooled = rebuild_pool_address_index();
+    if pooled > 0 {
+        log!(INFO, "Indexed the pool addresses of {} games", pooled);
+    }
+
+    let named = rebuild_game_name_index();
+    if named > 0 {
+        log!(INFO, "Indexed {} game names", named);
+    }
+
+    let ordered = rebuild_game_order_index();
+    if ordered > 0 {
+        log!(INFO, "Indexed {} games for listing", ordered);
+    }
+
+    let registered = rebuild_gamer_games_index();
+    if registered > 0 {
+        log!(INFO, "Indexed {} gamers by address", registered);
+    }
+
+    let scheduled = deadline::rebuild_game_end_index();
+    if scheduled > 0 {
+        log!(INFO, "Indexed {} games by end time", scheduled);
+    }
+
+    let etching = etching::rebuild_etch_check_index();
+    if etching > 0 {
+        log!(INFO, "Indexed {} etching games by next check", etching);
+    }
+
+    let indexed = leaderboard::rebuild_leaderboards();
+    if indexed > 0 {
+        log!(INFO, "Indexed {} gamers into the leaderboards", indexed);
+    }
 
     log!(
         INFO,
@@ -363,3 +622,169 @@ fn post_upgrade() {
         env!("CARGO_PKG_VERSION")
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_game(game_id: &str, gamers: &[&str]) {
+        let mut game = Game::new(
+            CreateGameArgs {
+                game_name: format!("claim {}", game_id),
+                gamer_register_fee: crate::DUST_BTC_VALUE,
+                claim_cooling_down: 10,
+                claim_amount_per_click: 10,
+                create_address: "creator".to_string(),
+                rune_premine_amount: 1000,
+                ..Default::default()
+            },
+            Principal::anonymous(),
+            game_id.to_string(),
+        );
+        game.game_status = GameStatus::Playing;
+        for gamer in gamers {
+            game.register_new_gamer(gamer.to_string()).unwrap();
+        }
+        insert_game(game);
+    }
+
+    #[test]
+    fn test_claim_games_reports_each_game() {
+        setup_game("1", &["alice"]);
+        setup_game("2", &["bob"]);
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect_vec();
+
+        let results = claim_games(
+            &Principal::anonymous(),
+            &"alice".to_string(),
+            ids(&["1", "missing", "2", "1"]),
+            100,
+        )
+        .unwrap();
+
+        // the repeated game is claimed once instead of failing on its cooldown
+        assert_eq!(
+            results.iter().map(|(id, _)| id.as_str()).collect_vec(),
+            vec!["1", "missing", "2"]
+        );
+        assert!(matches!(results[0].1, Ok(10)));
+        assert!(matches!(results[1].1, Err(ExchangeError::GameNotFound(_))));
+        assert!(matches!(results[2].1, Err(ExchangeError::GamerNotFound(_))));
+        assert_eq!(get_game(&"1".to_string()).unwrap().claimed_cookies, 10);
+    }
+
+    fn caller(is_controller: bool) -> Caller {
+        Caller {
+            principal: Principal::anonymous(),
+            is_controller,
+        }
+    }
+
+    fn stranger(is_controller: bool) -> Caller {
+        Caller {
+            principal: Principal::from_slice(&[7]),
+            is_controller,
+        }
+    }
+
+    fn game_status(game_id: &str) -> GameStatus {
+        get_game(&game_id.to_string()).unwrap().game_status
+    }
+
+    fn set_game_status(game_id: &str, status: GameStatus) {
+        mutate_game(&game_id.to_string(), |game| {
+            game.game_status = status;
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_restart_etching_of_failed_game() {
+        setup_game("1", &[]);
+        mutate_game(&"1".to_string(), |game| {
+            game.game_status = GameStatus::EtchingFailed("boom".to_string());
+            game.etch_rune_commit_tx = "commit_tx".to_string();
+            game.etch_check_count = 3;
+            Ok(())
+        })
+        .unwrap();
+
+        let game = restart_etching(&caller(false), &"1".to_string()).unwrap();
+        assert!(matches!(game.game_status, GameStatus::Etching));
+        assert!(game.etch_rune_commit_tx.is_empty());
+        assert_eq!(game.etch_check_count, 0);
+        assert!(matches!(game_status("1"), GameStatus::Etching));
+    }
+
+    #[test]
+    fn test_only_creator_restarts_etching() {
+        setup_game("1", &[]);
+        set_game_status("1", GameStatus::EtchingFailed("boom".to_string()));
+
+        for caller in [stranger(false), stranger(true)] {
+            assert!(restart_etching(&caller, &"1".to_string()).is_err());
+        }
+        assert!(matches!(game_status("1"), GameStatus::EtchingFailed(_)));
+    }
+
+    #[test]
+    fn test_restart_etching_requires_failed_etching() {
+        setup_game("1", &[]);
+        for status in [
+            GameStatus::Etching,
+            GameStatus::Playing,
+            GameStatus::Paused,
+            GameStatus::WaitAddedLiquidity,
+            GameStatus::Withdrawing,
+            GameStatus::Cancelled,
+        ] {
+            set_game_status("1", status);
+            assert!(restart_etching(&caller(false), &"1".to_string()).is_err());
+        }
+        assert!(matches!(game_status("1"), GameStatus::Cancelled));
+    }
+
+    #[test]
+    fn test_only_creator_or_controller_updates_game_status() {
+        setup_game("1", &[]);
+        let game_id = || "1".to_string();
+
+        assert!(update_game_status(&stranger(false), game_id(), GameStatus::pause).is_err());
+        assert!(matches!(game_status("1"), GameStatus::Playing));
+
+        update_game_status(&caller(false), game_id(), GameStatus::pause).unwrap();
+        assert!(matches!(game_status("1"), GameStatus::Paused));
+        update_game_status(&stranger(true), game_id(), GameStatus::resume).unwrap();
+        assert!(matches!(game_status("1"), GameStatus::Playing));
+
+        // a cancelled game can't be resumed by anyone
+        update_game_status(&caller(false), game_id(), GameStatus::cancel).unwrap();
+        assert!(update_game_status(&caller(false), game_id(), GameStatus::resume).is_err());
+        assert!(update_game_status(&stranger(true), game_id(), GameStatus::resume).is_err());
+        assert!(matches!(game_status("1"), GameStatus::Cancelled));
+    }
+
+    #[test]
+    fn test_only_controllers_change_the_config() {
+        let etch_canister = Principal::from_slice(&[1]);
+        let args = || UpgradeArgs {
+            etch_canister: Some(etch_canister),
+            ..Default::default()
+        };
+
+        assert!(change_config(&caller(false), args()).is_err());
+        assert_ne!(read_config(|c| c.etch_canister), etch_canister);
+        change_config(&caller(true), args()).unwrap();
+        assert_eq!(read_config(|c| c.etch_canister), etch_canister);
+    }
+
+    #[test]
+    fn test_claim_games_rejects_too_many_games() {
+        let game_ids = (0..=MAX_PAGE_SIZE).map(|id| id.to_string()).collect_vec();
+        assert!(matches!(
+            claim_games(&Principal::anonymous(), &"alice".to_string(), game_ids, 100),
+            Err(ExchangeError::TooManyGames(MAX_PAGE_SIZE))
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+
+use crate::exchange::exchange::{Config, __CustomStorageAccess};
+use crate::*;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BitcoinNetwork {
+    Mainnet,
+    // Canisters deployed before the network became configurable all ran on testnet4.
+    #[default]
+    Testnet4,
+    Regtest,
+}
+
+impl From<BitcoinNetwork> for ree_exchange_sdk::Network {
+    fn from(network: BitcoinNetwork) -> Self {
+        match network {
+            BitcoinNetwork::Mainnet => ree_exchange_sdk::Network::Bitcoin,
+            BitcoinNetwork::Testnet4 => ree_exchange_sdk::Network::Testnet4,
+            BitcoinNetwork::Regtest => ree_exchange_sdk::Network::Regtest,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct CanisterConfig {
+    pub network: BitcoinNetwork,
+    pub siwb_canister: Principal,
+    pub rune_indexer_canister: Principal,
+    pub orchestrator_canister: Principal,
+    pub icp_ledger_canister: Principal,
+    pub etch_canister: Principal,
+}
+
+impl Default for CanisterConfig {
+    fn default() -> Self {
+        Self {
+            network: BitcoinNetwork::default(),
+            siwb_canister: Principal::from_text(SIWB_TESTNET_CANISTER).unwrap(),
+            rune_indexer_canister: Principal::from_text(RUNE_INDEXER_CANISTER).unwrap(),
+            orchestrator_canister: Principal::from_text(ORCHESTRATOR_CANISTER).unwrap(),
+            icp_ledger_canister: Principal::from_text(ICP_LEDGER_CANISTER_ID).unwrap(),
+            etch_canister: Principal::from_text(ETCH_CANISTER_ID).unwrap(),
+        }
+    }
+}
+
+impl CanisterConfig {
+    pub fn update(&mut self, args: UpgradeArgs) {
+        let UpgradeArgs {
+            siwb_canister,
+            rune_indexer_canister,
+            orchestrator_canister,
+            icp_ledger_canister,
+            etch_canister,
+        } = args;
+        if let Some(p) = siwb_canister {
+            self.siwb_canister = p;
+        }
+        if let Some(p) = rune_indexer_canister {
+            self.rune_indexer_canister = p;
+        }
+        if let Some(p) = orchestrator_canister {
+            self.orchestrator_canister = p;
+        }
+        if let Some(p) = icp_ledger_canister {
+            self.icp_ledger_canister = p;
+        }
+        if let Some(p) = etch_canister {
+            self.etch_canister = p;
+        }
+    }
+}
+
+impl Storable for CanisterConfig {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(self).unwrap();
+        std::borrow::Cow::Owned(bytes)
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        bincode::serialize(&self).unwrap()
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Principals left as `None` fall back to the production deployments in `lib.rs`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct InitArgs {
+    pub network: BitcoinNetwork,
+    pub siwb_canister: Option<Principal>,
+    pub rune_indexer_canister: Option<Principal>,
+    pub orchestrator_canister: Option<Principal>,
+    pub icp_ledger_canister: Option<Principal>,
+    pub etch_canister: Option<Principal>,
+}
+
+/// Only the principals that are `Some` are changed. The network is fixed at install time,
+/// since every pool address was derived for it.
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct UpgradeArgs {
+    pub siwb_canister: Option<Principal>,
+    pub rune_indexer_canister: Option<Principal>,
+    pub orchestrator_canister: Option<Principal>,
+    pub icp_ledger_canister: Option<Principal>,
+    pub etch_canister: Option<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum CookieArgs {
+    Init(InitArgs),
+    Upgrade(Option<UpgradeArgs>),
+}
+
+impl From<InitArgs> for CanisterConfig {
+    fn from(args: InitArgs) -> Self {
+        let mut config = CanisterConfig {
+            network: args.network,
+            ..Default::default()
+        };
+        config.update(UpgradeArgs {
+            siwb_canister: args.siwb_canister,
+            rune_indexer_canister: args.rune_indexer_canister,
+            orchestrator_canister: args.orchestrator_canister,
+            icp_ledger_canister: args.icp_ledger_canister,
+            etch_canister: args.etch_canister,
+        });
+        config
+    }
+}
+
+pub fn read_config<F, R>(f: F) -> R
+where
+    F: FnOnce(&CanisterConfig) -> R,
+{
+    Config::with(|c| match c.get().as_ref() {
+        Some(config) => f(config),
+        None => f(&CanisterConfig::default()),
+    })
+}
+
+pub fn mutate_config<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut CanisterConfig) -> R,
+{
+    Config::with_mut(|c| {
+        let mut config = c.get().clone().unwrap_or_default();
+        let r = f(&mut config);
+        c.set(Some(config));
+        r
+    })
+}
+
+/// The bitcoin network every pool address and chain-key derivation of this canister uses.
+pub fn network() -> BitcoinNetwork {
+    read_config(|c| c.network)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_args_fall_back_to_defaults() {
+        let siwb_canister = Principal::from_slice(&[1]);
+        let config = CanisterConfig::from(InitArgs {
+            network: BitcoinNetwork::Mainnet,
+            siwb_canister: Some(siwb_canister),
+            rune_indexer_canister: None,
+            orchestrator_canister: None,
+            icp_ledger_canister: None,
+            etch_canister: None,
+        });
+        let defaults = CanisterConfig::default();
+
+        assert_eq!(config.network, BitcoinNetwork::Mainnet);
+        assert_eq!(config.siwb_canister, siwb_canister);
+        assert_eq!(config.rune_indexer_canister, defaults.rune_indexer_canister);
+        assert_eq!(config.orchestrator_canister, defaults.orchestrator_canister);
+        assert_eq!(config.icp_ledger_canister, defaults.icp_ledger_canister);
+        assert_eq!(config.etch_canister, defaults.etch_canister);
+    }
+
+    #[test]
+    fn test_update_changes_only_given_principals() {
+        let orchestrator_canister = Principal::from_slice(&[2]);
+        let mut config = CanisterConfig {
+            network: BitcoinNetwork::Regtest,
+            ..Default::default()
+        };
+        config.update(UpgradeArgs {
+            orchestrator_canister: Some(orchestrator_canister),
+            ..Default::default()
+        });
+        let defaults = CanisterConfig::default();
+
+        assert_eq!(config.network, BitcoinNetwork::Regtest);
+        assert_eq!(config.orchestrator_canister, orchestrator_canister);
+        assert_eq!(config.siwb_canister, defaults.siwb_canister);
+        assert_eq!(config.rune_indexer_canister, defaults.rune_indexer_canister);
+        assert_eq!(config.icp_ledger_canister, defaults.icp_ledger_canister);
+        assert_eq!(config.etch_canister, defaults.etch_canister);
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use crate::{
+    exchange::exchange::{mutate_game, GameEndTimes, Games, __CustomStorageAccess},
+    game::game::{Game, GameStatus},
+    utils::get_chain_second_timestamp,
+    *,
+};
+
+/// How often the canister ends games whose `end_time` has passed.
+pub const DEADLINE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Starts the background job that ends expired games, must be called on init and post_upgrade.
+pub fn schedule_deadline_checks() {
+    ic_cdk_timers::set_timer_interval(DEADLINE_CHECK_INTERVAL, end_expired_games);
+}
+
+/// Puts a game with an end time into the index the deadline job walks.
+pub fn schedule_game_end(game: &Game) {
+    if let Some(end_time) = game.end_time {
+        GameEndTimes::with_mut(|m| {
+            m.insert((end_time, game.game_id.clone()), ());
+        });
+    }
+}
+
+/// Fills the end time index from the games created before it existed, returns the number
+/// of games indexed. Does nothing once any game is indexed.
+pub fn rebuild_game_end_index() -> usize {
+    if !GameEndTimes::with(|m| m.is_empty()) {
+        return 0;
+    }
+    let games = Games::with(|m| {
+        m.iter()
+            .map(|e| e.value())
+            .filter(|game| game.end_time.is_some() && !is_past_end(&game.game_status))
+            .collect::<Vec<_>>()
+    });
+    games.iter().for_each(schedule_game_end);
+    games.len()
+}
+
+/// Games also end lazily on the next claim or add_liquidity, this only keeps statuses fresh.
+pub fn end_expired_games() {
+    end_games_due(get_chain_second_timestamp());
+}
+
+/// Ends the games whose end time is not after `now`, loading only those games.
+pub fn end_games_due(now: SecondTimestamp) {
+    let due = GameEndTimes::with(|m| {
+        m.iter()
+            .map(|e| e.key().clone())
+            .take_while(|(end_time, _)| *end_time <= now)
+            .collect::<Vec<_>>()
+    });
+
+    for (end_time, game_id) in due {
+        let result = mutate_game(&game_id, |game| {
+            let ended = game.end_if_expired(now)?;
+            Ok((ended, game.game_status.clone()))
+        });
+        let done = match result {
+            Ok((true, _)) => {
+                log!(INFO, "Game {} ended at its end time", game_id);
+                true
+            }
+            // games still etching are ended once they start playing
+            Ok((false, status)) => is_past_end(&status),
+            Err(ExchangeError::GameNotFound(_)) => true,
+            Err(e) => {
+                log!(ERROR, "Failed to end game {}: {}", game_id, e);
+                false
+            }
+        };
+        if done {
+            GameEndTimes::with_mut(|m| m.remove(&(end_time, game_id)));
+        }
+    }
+}
+
+/// Whether a game in `status` can no longer be ended at its end time.
+fn is_past_end(status: &GameStatus) -> bool {
+    matches!(
+        status,
+        GameStatus::WaitAddedLiquidity | GameStatus::Withdrawing | GameStatus::Cancelled
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::exchange::{get_game, insert_game};
+    use crate::game::game::CreateGameArgs;
+
+    fn game(game_id: &str, end_time: SecondTimestamp, game_status: GameStatus) -> Game {
+        let mut game = Game::new(
+            CreateGameArgs {
+                game_name: format!("deadline {}", game_id),
+                gamer_register_fee: DUST_BTC_VALUE,
+                claim_cooling_down: 10,
+                claim_amount_per_click: 10,
+                create_address: "creator".to_string(),
+                rune_premine_amount: 1000,
+                end_time: Some(end_time),
+                ..Default::default()
+            },
+            Principal::anonymous(),
+            game_id.to_string(),
+        );
+        game.game_status = game_status;
+        schedule_game_end(&game);
+        insert_game(game.clone());
+        game
+    }
+
+    fn indexed() -> Vec<GameId> {
+        GameEndTimes::with(|m| m.iter().map(|e| e.key().1.clone()).collect())
+    }
+
+    #[test]
+    fn test_end_games_due_only_ends_expired_games() {
+        game("1", 100, GameStatus::Playing);
+        game("2", 200, GameStatus::Playing);
+        game("3", 50, GameStatus::Etching);
+
+        end_games_due(150);
+
+        assert!(matches!(
+            get_game(&"1".to_string()).unwrap().game_status,
+            GameStatus::WaitAddedLiquidity
+        ));
+        assert!(matches!(
+            get_game(&"2".to_string()).unwrap().game_status,
+            GameStatus::Playing
+        ));
+        // the etching game stays indexed until it can be ended
+        assert_eq!(indexed(), vec!["3".to_string(), "2".to_string()]);
+    }
+
+    #[test]
+    fn test_end_games_due_drops_cancelled_games() {
+        game("1", 100, GameStatus::Cancelled);
+
+        end_games_due(100);
+
+        assert!(indexed().is_empty());
+        assert!(matches!(
+            get_game(&"1".to_string()).unwrap().game_status,
+            GameStatus::Cancelled
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{game::game::GameStatus, *};
+use crate::{anti_bot::ClaimViolation, game::game::GameStatus, *};
 use candid::Nat;
 use ic_cdk::api::call::RejectionCode;
 use thiserror::Error;
@@ -71,10 +71,16 @@ pub enum ExchangeError {
     GameNotEnd,
     #[error("Game End")]
     GameEnd,
+    #[error("Game Paused: {0}")]
+    GamePaused(GameId),
     #[error("Gamer Not Found, {0}")]
     GamerNotFound(AddressStr),
     #[error("Gamer Withdraw Repeatedly, {0}")]
     GamerWithdrawRepeatedly(AddressStr),
+    #[error("Gamer Refund Repeatedly, {0}")]
+    GamerRefundRepeatedly(AddressStr),
+    #[error("Game Not Refundable: {0}")]
+    GameNotRefundable(GameId),
     #[error("Gamer Already Exist, {0}")]
     GamerAlreadyExist(AddressStr),
     #[error("Gamer Cooling Down, {0} next claimable timestamp {1}")]
@@ -92,6 +98,43 @@ pub enum ExchangeError {
         actual: AddressStr,
     },
 
+    #[error("Invalid action params: {0}")]
+    InvalidActionParams(String),
+    #[error("Invalid withdraw coins: {0}")]
+    InvalidWithdrawCoins(String),
+    #[error("Invalid refund coins: {0}")]
+    InvalidRefundCoins(String),
+    #[error("Fail to fetch principal: {0}")]
+    FetchPrincipalError(String),
+    #[error("Invalid token split: {0}")]
+    InvalidTokenSplit(String),
+    #[error("Not the game creator: {0}")]
+    NotGameCreator(AddressStr),
+    #[error("No creator allocation in game: {0}")]
+    NoCreatorAllocation(GameId),
+    #[error("Creator Withdraw Repeatedly, game {0}")]
+    CreatorWithdrawRepeatedly(GameId),
+    #[error("Invalid game args: {0}")]
+    InvalidGameArgs(String),
+    #[error("Game name too long, at most {0} characters")]
+    GameNameTooLong(usize),
+    #[error("Game name already exist: {0}")]
+    GameNameAlreadyExist(String),
+    #[error("Register fee {0} is below the dust value {1}")]
+    RegisterFeeTooSmall(u64, u64),
+    #[error("Caller is not the owner of address {0}")]
+    CallerNotAddressOwner(AddressStr),
+    #[error("Claim rejected: {0:?}")]
+    ClaimRejected(ClaimViolation),
+    #[error("Gamer can't refer itself, {0}")]
+    SelfReferral(AddressStr),
+    #[error("Referrer not registered in the game, {0}")]
+    ReferrerNotFound(AddressStr),
+    #[error("Gamer Already Referred, {0}")]
+    AlreadyReferred(AddressStr),
+    #[error("At most {0} games can be claimed at once")]
+    TooManyGames(u64),
+
     #[error("{0}")]
     CustomError(String),
 }
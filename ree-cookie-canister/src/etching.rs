@@ -0,0 +1,387 @@
+use std::cell::Cell;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{
+    config,
+    exchange::exchange::{
+        get_game, index_pool_addresses, mutate_game, new_empty_pool, new_pool_by_utxo,
+        EtchChecks, Games, __CustomStorageAccess,
+    },
+    external::{
+        etch_canister::{get_etching_request, EtchingStatus},
+        rune_indexer::get_etching,
+    },
+    game::game::{Game, GameStatus},
+    utils::get_chain_second_timestamp,
+    *,
+};
+use ic_stable_structures::Storable;
+use ree_exchange_sdk::{
+    prelude::schnorr::request_p2tr_address,
+    types::{CoinBalance, CoinBalances, CoinId},
+};
+
+/// How often the canister looks for games whose etching may have completed.
+pub const ETCHING_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+thread_local! {
+    static ETCHING_CHECK_RUNNING: Cell<bool> = Cell::new(false);
+}
+
+/// Holds the etching check flag, released on drop so a trapped callback can't leave it set.
+struct EtchingCheckGuard;
+
+impl EtchingCheckGuard {
+    fn acquire() -> Option<Self> {
+        if ETCHING_CHECK_RUNNING.with(|r| r.replace(true)) {
+            return None;
+        }
+        Some(EtchingCheckGuard)
+    }
+}
+
+impl Drop for EtchingCheckGuard {
+    fn drop(&mut self) {
+        ETCHING_CHECK_RUNNING.with(|r| r.set(false));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EtchingProgress {
+    /// The etching or its confirmation is not there yet, with the reason.
+    Pending(String),
+    /// The pool was created and the game is playable, with the reveal txid.
+    Finalized(String),
+    /// The etching canister gave up, the game is now `EtchingFailed` with this error.
+    Failed(String),
+}
+
+/// Starts the background job that finalizes etchings, must be called on init and post_upgrade.
+pub fn schedule_etching_checks() {
+    ic_cdk_timers::set_timer_interval(ETCHING_CHECK_INTERVAL, || {
+        ic_cdk::futures::spawn(check_etching_games())
+    });
+}
+
+async fn check_etching_games() {
+    let Some(_guard) = EtchingCheckGuard::acquire() else {
+        return;
+    };
+
+    let now = get_chain_second_timestamp();
+    for (check_time, game_id) in due_etch_checks(now) {
+        // entries of games that left Etching or were checked meanwhile are dropped
+        let due = get_game(&game_id).is_some_and(|game| {
+            game.next_etch_check_time == check_time && game.is_etch_check_due(now)
+        });
+        if !due {
+            EtchChecks::with_mut(|m| m.remove(&(check_time, game_id)));
+            continue;
+        }
+        match finalize_etching(game_id.clone()).await {
+            Ok(EtchingProgress::Finalized(reveal_txid)) => {
+                log!(
+                    INFO,
+                    "Etching of game {} finalized, reveal tx {}",
+                    game_id,
+                    reveal_txid
+                );
+            }
+            Ok(EtchingProgress::Pending(reason)) => {
+                log!(DEBUG, "Etching of game {} pending: {}", game_id, reason);
+            }
+            Ok(EtchingProgress::Failed(err_info)) => {
+                log!(WARNING, "Etching of game {} failed: {}", game_id, err_info);
+            }
+            Err(e) => {
+                log!(ERROR, "Failed to finalize etching of game {}: {}", game_id, e);
+            }
+        }
+    }
+}
+
+/// Puts an etching game whose commit tx is known into the index the etching job walks.
+pub fn schedule_etch_check(game: &Game) {
+    if matches!(game.game_status, GameStatus::Etching) && !game.etch_rune_commit_tx.is_empty() {
+        EtchChecks::with_mut(|m| {
+            m.insert((game.next_etch_check_time, game.game_id.clone()), ());
+        });
+    }
+}
+
+/// Drops the index entry of a game, must be called before its next check time changes.
+pub fn unschedule_etch_check(game: &Game) {
+    EtchChecks::with_mut(|m| m.remove(&(game.next_etch_check_time, game.game_id.clone())));
+}
+
+/// Fills the etching check index from the games etched before it existed, returns the
+/// number of games indexed. Does nothing once any game is indexed.
+pub fn rebuild_etch_check_index() -> usize {
+    if !EtchChecks::with(|m| m.is_empty()) {
+        return 0;
+    }
+    let games = Games::with(|m| {
+        m.iter()
+            .map(|e| e.value())
+            .filter(|game| {
+                matches!(game.game_status, GameStatus::Etching)
+                    && !game.etch_rune_commit_tx.is_empty()
+            })
+            .collect::<Vec<_>>()
+    });
+    games.iter().for_each(schedule_etch_check);
+    games.len()
+}
+
+fn due_etch_checks(now: SecondTimestamp) -> Vec<(SecondTimestamp, GameId)> {
+    EtchChecks::with(|m| {
+        m.iter()
+            .map(|e| e.key().clone())
+            .take_while(|(check_time, _)| *check_time <= now)
+            .collect()
+    })
+}
+
+/// Checks the etching of a game and creates its pool once the reveal tx is confirmed.
+/// Every outcome other than `Finalized` is recorded on the game and pushes back its next check.
+pub async fn finalize_etching(game_id: GameId) -> std::result::Result<EtchingProgress, String> {
+    let result = try_finalize_etching(game_id.clone()).await;
+    let now = get_chain_second_timestamp();
+    let error = match &result {
+        Ok(EtchingProgress::Finalized(_)) | Ok(EtchingProgress::Failed(_)) => return result,
+        Ok(EtchingProgress::Pending(reason)) => reason.clone(),
+        Err(e) => e.clone(),
+    };
+    let _ = mutate_game(&game_id, |game| {
+        if matches!(game.game_status, GameStatus::Etching) {
+            unschedule_etch_check(game);
+            game.record_etch_check_failure(error, now);
+            schedule_etch_check(game);
+        }
+        Ok(())
+    });
+    result
+}
+
+async fn try_finalize_etching(game_id: GameId) -> std::result::Result<EtchingProgress, String> {
+    let game = get_game(&game_id).ok_or_else(|| format!("Game with ID {} not found", game_id))?;
+    if !matches!(game.game_status, GameStatus::Etching) {
+        return Err(format!(
+            "Game {} is not etching, current status: {:?}",
+            game_id, game.game_status
+        ));
+    }
+    if game.etch_rune_commit_tx.is_empty() {
+        return Ok(EtchingProgress::Pending("Rune not etched yet".to_string()));
+    }
+
+    // query reveal tx id from etching canister
+    let info = match get_etching_request(game.etch_rune_commit_tx.clone()).await {
+        Some(info) => info,
+        None => {
+            return Ok(EtchingProgress::Pending(
+                "Failed to get etching request".to_string(),
+            ))
+        }
+    };
+
+    match info.status {
+        EtchingStatus::Final => {}
+        EtchingStatus::SendCommitFailed | EtchingStatus::SendRevealFailed => {
+            let err_info = format!("{:?}: {}", info.status, info.err_info);
+            mutate_game(&game_id, |game| {
+                game.fail_etching(err_info.clone())?;
+                unschedule_etch_check(game);
+                Ok(())
+            })
+            .map_err(|e| e.to_string())?;
+            return Ok(EtchingProgress::Failed(err_info));
+        }
+        status => {
+            return Ok(EtchingProgress::Pending(format!(
+                "Etching not finalized yet, status: {:?}",
+                status
+            )));
+        }
+    }
+
+    let reveal_tx_id = info.reveal_txid;
+
+    let result = match get_etching(reveal_tx_id.clone()).await {
+        Some(result) => result,
+        None => {
+            return Ok(EtchingProgress::Pending(
+                "Failed to get etching result".to_string(),
+            ))
+        }
+    };
+
+    if result.confirmations < 1 {
+        return Ok(EtchingProgress::Pending(
+            "Etching not confirmed yet".to_string(),
+        ));
+    }
+
+    let rune_id = CoinId::from_str(result.rune_id.as_str())
+        .map_err(|e| format!("Failed to parse rune ID from etching result: {:?}", e))?;
+    let premine = info
+        .etching_args
+        .premine
+        .ok_or("Premine amount not found".to_string())?;
+    let reveal_txid =
+        Txid::from_str(&reveal_tx_id).map_err(|e| format!("Failed to parse reveal tx id: {:?}", e))?;
+
+    let key_path = game.key_path();
+    let (pubkey, _tweaked_pubkey, pool_address) = request_p2tr_address(
+        vec![key_path.to_bytes().to_vec()],
+        config::network().into(),
+    )
+    .await
+    .map_err(|e| format!("Failed to request address: {:?}", e))?;
+
+    let mut fee_pools = vec![];
+    for shard in 1..game.pool_shards {
+        let key_path = game.shard_key_path(shard);
+        let (pubkey, _tweaked_pubkey, address) =
+            request_p2tr_address(vec![key_path.to_bytes().to_vec()], config::network().into())
+                .await
+                .map_err(|e| format!("Failed to request address of shard {}: {:?}", shard, e))?;
+        fee_pools.push((key_path, pubkey, address.to_string()));
+    }
+
+    // nothing is awaited from here on, but another check may have finalized the game meanwhile
+    mutate_game(&game_id, |game| {
+        if !matches!(game.game_status, GameStatus::Etching) {
+            return Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Etching,
+                game.game_status.clone(),
+            ));
+        }
+        let mut rune_info = game
+            .rune_info
+            .clone()
+            .ok_or(ExchangeError::RuneNotFound(game.game_name.clone()))?;
+
+        let mut coin_balances = CoinBalances::new();
+        coin_balances.add_coin(&CoinBalance {
+            id: CoinId::btc(),
+            value: DUST_BTC_VALUE as u128,
+        });
+        coin_balances.add_coin(&CoinBalance {
+            id: rune_id.clone(),
+            value: premine,
+        });
+
+        new_pool_by_utxo(
+            key_path.clone(),
+            key_path,
+            pubkey,
+            pool_address.to_string(),
+            Utxo {
+                txid: reveal_txid,
+                vout: 1,
+                coins: coin_balances,
+                sats: DUST_BTC_VALUE,
+            },
+        );
+
+        game.fee_pool_addresses = vec![];
+        for (key_path, pubkey, address) in fee_pools {
+            new_empty_pool(key_path.clone(), key_path, pubkey, address.clone());
+            game.fee_pool_addresses.push(address);
+        }
+
+        rune_info.rune_id = rune_id;
+        game.rune_info = Some(rune_info);
+        game.pool_address = Some(pool_address.to_string());
+        game.game_status = game.game_status.finish_etching()?;
+        game.last_active_time = get_chain_second_timestamp();
+        unschedule_etch_check(game);
+        game.clear_etch_checks();
+        index_pool_addresses(game);
+        Ok(())
+    })
+    .map_err(|e| e.to_string())?;
+
+    Ok(EtchingProgress::Finalized(reveal_tx_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::exchange::insert_game;
+    use crate::game::game::CreateGameArgs;
+
+    fn game(game_id: &str, commit_tx: &str, game_status: GameStatus) -> Game {
+        let mut game = Game::new(
+            CreateGameArgs {
+                game_name: format!("etching {}", game_id),
+                gamer_register_fee: DUST_BTC_VALUE,
+                claim_cooling_down: 10,
+                claim_amount_per_click: 10,
+                create_address: "creator".to_string(),
+                rune_premine_amount: 1000,
+                ..Default::default()
+            },
+            Principal::anonymous(),
+            game_id.to_string(),
+        );
+        game.game_status = game_status;
+        game.etch_rune_commit_tx = commit_tx.to_string();
+        insert_game(game.clone());
+        game
+    }
+
+    fn indexed() -> Vec<(SecondTimestamp, GameId)> {
+        EtchChecks::with(|m| m.iter().map(|e| e.key().clone()).collect())
+    }
+
+    #[test]
+    fn test_etch_checks_follow_the_backoff() {
+        let mut first = game("1", "commit_1", GameStatus::Etching);
+        let second = game("2", "commit_2", GameStatus::Etching);
+        schedule_etch_check(&first);
+        schedule_etch_check(&second);
+
+        unschedule_etch_check(&first);
+        first.record_etch_check_failure("pending".to_string(), 100);
+        schedule_etch_check(&first);
+
+        assert_eq!(due_etch_checks(100), vec![(0, "2".to_string())]);
+        assert_eq!(
+            due_etch_checks(220),
+            vec![(0, "2".to_string()), (220, "1".to_string())]
+        );
+
+        unschedule_etch_check(&second);
+        assert_eq!(indexed(), vec![(220, "1".to_string())]);
+    }
+
+    #[test]
+    fn test_etch_checks_skip_games_not_etched() {
+        let unetched = game("1", "", GameStatus::Etching);
+        let failed = game(
+            "2",
+            "commit_2",
+            GameStatus::EtchingFailed("failed".to_string()),
+        );
+        schedule_etch_check(&unetched);
+        schedule_etch_check(&failed);
+
+        assert!(indexed().is_empty());
+    }
+
+    #[test]
+    fn test_rebuild_etch_check_index() {
+        game("1", "commit_1", GameStatus::Etching);
+        game("2", "", GameStatus::Etching);
+        game("3", "commit_3", GameStatus::Playing);
+
+        assert_eq!(rebuild_etch_check_index(), 1);
+        assert_eq!(indexed(), vec![(0, "1".to_string())]);
+
+        game("4", "commit_4", GameStatus::Etching);
+        assert_eq!(rebuild_etch_check_index(), 0);
+    }
+}
@@ -4,12 +4,64 @@ use candid::CandidType;
 use ic_stable_structures::{storable::Bound, Storable};
 use ree_exchange_sdk::prelude::*;
 use ree_exchange_sdk::{
-    types::{CoinBalance, Txid, Utxo},
+    types::{CoinBalance, CoinId, Txid, Utxo},
     StateInfo, StateView,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{AddressStr, GameId};
+use crate::{AddressStr, ExchangeError, GameId};
+
+/// The `action_params` JSON shared by the register, add_liquidity and withdraw actions.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ActionParams {
+    #[serde(deserialize_with = "deserialize_game_id")]
+    pub game_id: GameId,
+    /// Fields not known to every action, kept so that single actions can read their own.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ActionParams {
+    pub fn parse(action_params: &str) -> crate::errors::Result<Self> {
+        serde_json::from_str(action_params)
+            .map_err(|e| ExchangeError::InvalidActionParams(e.to_string()))
+    }
+
+    /// The optional `referrer` address of the register action.
+    pub fn referrer(&self) -> crate::errors::Result<Option<AddressStr>> {
+        match self.extra.get("referrer") {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(serde_json::Value::String(referrer)) if !referrer.is_empty() => {
+                Ok(Some(referrer.clone()))
+            }
+            Some(referrer) => Err(ExchangeError::InvalidActionParams(format!(
+                "referrer must be a non-empty address, got {}",
+                referrer
+            ))),
+        }
+    }
+}
+
+/// Game ids are generated as decimal strings, but clients may send them as JSON numbers.
+fn deserialize_game_id<'de, D>(deserializer: D) -> std::result::Result<GameId, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawGameId {
+        Text(String),
+        Number(u64),
+    }
+
+    match RawGameId::deserialize(deserializer)? {
+        RawGameId::Text(id) if id.is_empty() => {
+            Err(serde::de::Error::custom("game_id must not be empty"))
+        }
+        RawGameId::Text(id) => Ok(id),
+        RawGameId::Number(id) => Ok(id.to_string()),
+    }
+}
 
 #[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Eq)]
 pub enum UserAction {
@@ -17,6 +69,37 @@ pub enum UserAction {
     AddLiquidity,
     Register(GameId, AddressStr),
     Withdraw(GameId, AddressStr),
+    Refund(GameId, AddressStr),
+    CreatorWithdraw(GameId),
+    BatchWithdraw(GameId, Vec<AddressStr>),
+}
+
+/// The game record a pool transaction has to update when it is confirmed or finalized.
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Eq)]
+pub enum TrackedTx {
+    Register(GameId, AddressStr),
+    AddLiquidity(GameId),
+    Withdraw(GameId, AddressStr),
+    Refund(GameId, AddressStr),
+    CreatorWithdraw(GameId),
+    BatchWithdraw(GameId, Vec<AddressStr>),
+}
+
+impl Storable for TrackedTx {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(bincode::serialize(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bincode::serialize_into(&mut bytes, &self).unwrap();
+        bytes
+    }
 }
 
 #[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
@@ -44,6 +127,17 @@ impl Storable for CookiePoolState {
     }
 }
 
+impl CookiePoolState {
+    pub fn coin_balance(&self, id: &CoinId) -> u128 {
+        self.utxo
+            .coins
+            .iter()
+            .filter(|c| c.id == *id)
+            .map(|c| c.value)
+            .sum()
+    }
+}
+
 impl StateView for CookiePoolState {
     fn inspect_state(&self) -> StateInfo {
         let mut coin_reserved: Vec<CoinBalance> = vec![]; // Placeholder, as CoinBalance is not defined in the provided context
@@ -71,7 +165,19 @@ pub mod exchange {
         types::{bitcoin, Intention, Pubkey},
     };
 
-    use crate::{external::internal_identity::get_principal, state::ExchangeState, utils::RuneCommitList};
+    use crate::{
+        anti_bot::ClaimWindow,
+        config::CanisterConfig,
+        external::internal_identity::get_principal,
+        game::{
+            game::{Game, GameSort, GameSummary, ListGamesArgs, MAX_PAGE_SIZE},
+            gamer::{Gamer, TxProgress, TxStatus},
+            leaderboard::{self, GamerStats},
+            referral::{self, Referral},
+        },
+        state::ExchangeState,
+        utils::{get_chain_second_timestamp, RuneCommitList},
+    };
 
     use super::*;
 
@@ -84,6 +190,82 @@ pub mod exchange {
     #[storage(2)]
     pub type ReceiverRunesMap = StableBTreeMap<AddressStr, RuneCommitList>;
 
+    #[storage(3)]
+    pub type Games = StableBTreeMap<GameId, Game>;
+
+    #[storage(4)]
+    pub type Gamers = StableBTreeMap<(GameId, AddressStr), Gamer>;
+
+    #[storage(5)]
+    pub type Config = StableCell<CanisterConfig>;
+
+    /// Pool transactions that are not finalized yet, keyed by txid.
+    #[storage(6)]
+    pub type TrackedTxs = StableBTreeMap<String, TrackedTx>;
+
+    /// The id the next created game gets, only ever counts up.
+    #[storage(7)]
+    pub type NextGameId = StableCell<u64>;
+
+    /// Gamers of a game ranked by cookies, see `game::leaderboard`.
+    #[storage(8)]
+    pub type GameLeaderboard = StableBTreeMap<(GameId, u128, AddressStr), ()>;
+
+    #[storage(9)]
+    pub type GamerStatsMap = StableBTreeMap<AddressStr, GamerStats>;
+
+    /// Gamers across all games ranked by total cookies, see `game::leaderboard`.
+    #[storage(10)]
+    pub type GlobalLeaderboard = StableBTreeMap<(u128, AddressStr), ()>;
+
+    /// Claims per principal in the current rate limit window, see `anti_bot`.
+    #[storage(11)]
+    pub type ClaimWindows = StableBTreeMap<Principal, ClaimWindow>;
+
+    /// The secret the claim challenges are derived from, see `anti_bot`.
+    #[storage(12)]
+    pub type ChallengeSalt = StableCell<Vec<u8>>;
+
+    /// Who referred whom in each game, keyed by game and referee, see `game::referral`.
+    #[storage(13)]
+    pub type Referrals = StableBTreeMap<(GameId, AddressStr), Referral>;
+
+    /// Games that may still have to be ended at their end time, see `deadline`.
+    #[storage(14)]
+    pub type GameEndTimes = StableBTreeMap<(crate::SecondTimestamp, GameId), ()>;
+
+    /// The game using each name, keyed by the trimmed name, so that names stay unique.
+    #[storage(15)]
+    pub type GameNames = StableBTreeMap<String, GameId>;
+
+    /// The games each address registered in, the reverse of the `Gamers` keys.
+    #[storage(16)]
+    pub type GamerGames = StableBTreeMap<(AddressStr, GameId), ()>;
+
+    /// `ClaimWindows` ordered by window start, so that expired windows can be evicted.
+    #[storage(17)]
+    pub type ClaimWindowStarts = StableBTreeMap<(crate::SecondTimestamp, Principal), ()>;
+
+    /// The game and shard of every pool address, see `find_game_by_pool_address`.
+    #[storage(18)]
+    pub type PoolAddresses = StableBTreeMap<AddressStr, (GameId, u8)>;
+
+    /// Etching games keyed by their next etching check, see `etching`.
+    #[storage(19)]
+    pub type EtchChecks = StableBTreeMap<(crate::SecondTimestamp, GameId), ()>;
+
+    /// Games in creation order, see `list_games`.
+    #[storage(20)]
+    pub type GamesByCreation = StableBTreeMap<(u64, GameId), ()>;
+
+    /// Games with the most gamers first, then in creation order, see `list_games`.
+    #[storage(21)]
+    pub type GamesByGamers = StableBTreeMap<(u64, u64, GameId), ()>;
+
+    /// Games with the most claimed cookies first, then in creation order, see `list_games`.
+    #[storage(22)]
+    pub type GamesByClaimed = StableBTreeMap<(u128, u64, GameId), ()>;
+
     #[pools]
     pub struct CookiePools;
 
@@ -97,7 +279,7 @@ pub mod exchange {
         const TRANSACTION_MEMORY: u8 = 102;
 
         fn network() -> Network {
-            Network::Testnet4
+            crate::config::network().into()
         }
 
         // This is optional
@@ -129,6 +311,32 @@ pub mod exchange {
         CookiePools::insert(pool);
     }
 
+    /// A pool without any state yet, its first transaction spends nothing and only receives.
+    pub fn new_empty_pool(
+        pool_name: String,
+        key_path: String,
+        pubkey: Pubkey,
+        address: AddressStr,
+    ) {
+        CookiePools::insert(Pool::new(Metadata {
+            key: pubkey,
+            key_derivation_path: vec![key_path.into_bytes()],
+            name: pool_name,
+            address: address.to_string(),
+        }));
+    }
+
+    fn rollback_withdraw(game: &mut Game, address: &AddressStr) {
+        if let Some(mut gamer) = game.get_gamer(address) {
+            if gamer.is_withdrawn {
+                let withdrawn = game.unsettle_withdraw(&mut gamer);
+                leaderboard::revert_withdraw(address, withdrawn);
+            }
+            gamer.withdraw_tx = None;
+            game.save_gamer(gamer);
+        }
+    }
+
     pub fn mutate_pool(
         pool_address: &AddressStr,
         f: impl FnOnce(&mut Pool<CookiePoolState>) -> std::result::Result<(), String>,
@@ -143,35 +351,77 @@ pub mod exchange {
     #[hook]
     impl Hook for CookiePools {
         fn on_tx_rollbacked(
-            _address: String,
+            address: String,
             _txid: Txid,
             _reason: String,
             rollbacked_states: Vec<CookiePoolState>,
         ) {
             for e in rollbacked_states {
+                TrackedTxs::with_mut(|m| m.remove(&e.txid.to_string()));
                 match e.user_action {
                     UserAction::Register(game_id, address) => {
-                        // State::with_mut(|es| {
-                        //     let game = es.games.get_mut(&game_id).unwrap();
-                        //     game.gamers.remove(&address);
-                        // });
-                        mutate_state(|es| {
-                            let game = es.games.get_mut(&game_id).unwrap();
-                            game.gamers.remove(&address);
+                        let _ = mutate_game(&game_id, |game| {
+                            game.remove_gamer(&address);
+                            Ok(())
                         });
                     }
                     UserAction::Withdraw(game_id, address) => {
-                        mutate_state(|es| {
-                            let game = es.games.get_mut(&game_id).unwrap();
-                            game.gamers
-                                .get_mut(&address)
-                                .map(|g| g.is_withdrawn = false);
+                        let _ = mutate_game(&game_id, |game| {
+                            rollback_withdraw(game, &address);
+                            Ok(())
+                        });
+                    }
+                    UserAction::BatchWithdraw(game_id, addresses) => {
+                        let _ = mutate_game(&game_id, |game| {
+                            for address in addresses.iter() {
+                                rollback_withdraw(game, address);
+                            }
+                            Ok(())
+                        });
+                    }
+                    UserAction::Refund(game_id, address) => {
+                        let _ = mutate_game(&game_id, |game| {
+                            if let Some(mut gamer) = game.get_gamer(&address) {
+                                gamer.is_refunded = false;
+                                gamer.refund_tx = None;
+                                game.save_gamer(gamer);
+                            }
+                            Ok(())
+                        });
+                    }
+                    UserAction::CreatorWithdraw(game_id) => {
+                        let _ = mutate_game(&game_id, |game| {
+                            game.is_creator_allocation_withdrawn = false;
+                            game.creator_withdraw_tx = None;
+                            Ok(())
                         });
                     }
-                    _ => {}
+                    UserAction::AddLiquidity => {
+                        let Some(game) = find_game_by_pool_address(&address) else {
+                            continue;
+                        };
+                        let _ = mutate_game(&game.game_id, |game| {
+                            if game.pool_shard(&address)? > 0 {
+                                game.fee_shards_with_liquidity.retain(|a| a != &address);
+                                return Ok(());
+                            }
+                            game.game_status = game.game_status.rollback_add_liquidity()?;
+                            game.add_liquidity_tx = None;
+                            Ok(())
+                        });
+                    }
+                    UserAction::Init => {}
                 }
             }
         }
+
+        fn on_tx_confirmed(_address: String, txid: Txid, _block: Block) {
+            advance_tracked_tx(txid, TxStatus::Confirmed, get_chain_second_timestamp());
+        }
+
+        fn on_tx_finalized(_address: String, txid: Txid, _block: Block) {
+            advance_tracked_tx(txid, TxStatus::Final, get_chain_second_timestamp());
+        }
     }
 
     #[action]
@@ -187,20 +437,10 @@ pub mod exchange {
             input_coins,
             output_coins,
         } = args.intention;
-        let action_params_json_value =
-            serde_json::from_str::<serde_json::Value>(action_params.as_str())
-                .map_err(|_| "invalid action params".to_string())?;
-        let game = read_state(|es| {
-            let game_id = action_params_json_value
-                .get("game_id")
-                .map(|v| v.to_string())
-                .ok_or("invalid game id".to_string())?;
-
-            es.games
-                .get(&game_id)
-                .cloned()
-                .ok_or("Game not found".to_string())
-        })?;
+        let params = ActionParams::parse(&action_params).map_err(|e| e.to_string())?;
+        let referrer = params.referrer().map_err(|e| e.to_string())?;
+        let game_id = params.game_id;
+        let game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
         let (new_state, (_key_derivation_path, _utxo)) = game
             .validate_register(
                 pool_address.clone(),
@@ -211,17 +451,36 @@ pub mod exchange {
                 input_coins,
                 output_coins,
                 args.initiator_address.clone(),
+                referrer.as_ref(),
             )
             .map_err(|e| e.to_string())?;
 
+        let shard = game.pool_shard(&pool_address).map_err(|e| e.to_string())?;
         let initiator = args.initiator_address.clone();
-        let principal_of_initiator = get_principal(initiator.clone()).await?;
-        mutate_state(|es| {
-            let game_id = game.game_id;
-            let game = es.games.get_mut(&game_id).ok_or("Game not found").unwrap();
-            game.register_new_gamer(initiator.clone())
-                .expect("Failed to register gamer");
-        });
+        let principal_of_initiator = get_principal(initiator.clone())
+            .await
+            .map_err(|e| ExchangeError::FetchPrincipalError(e).to_string())?;
+        mutate_game(&game.game_id, |game| {
+            // the graph may have changed while fetching the principal
+            if let Some(referrer) = &referrer {
+                referral::validate_referral(game, &initiator, referrer)?;
+            }
+            game.register_new_gamer(initiator.clone())?;
+            game.last_active_time = get_chain_second_timestamp();
+            game.update_gamer(&initiator, |gamer| {
+                gamer.register_tx = Some(TxProgress::new(args.txid.clone()));
+            })?;
+            game.assign_pool_shard(&initiator, shard)?;
+            match &referrer {
+                Some(referrer) => referral::record_referral(game, &initiator, referrer),
+                None => Ok(()),
+            }
+        })
+        .map_err(|e| e.to_string())?;
+        track_tx(
+            args.txid.clone(),
+            TrackedTx::Register(game.game_id.clone(), initiator.clone()),
+        );
         AddressPrincipalMap::with_mut(|m| {
             m.insert(principal_of_initiator, initiator.clone());
         });
@@ -244,20 +503,13 @@ pub mod exchange {
             input_coins,
             output_coins,
         } = args.intention;
-        let action_params_json_value =
-            serde_json::from_str::<serde_json::Value>(action_params.as_str())
-                .map_err(|_| "invalid action params".to_string())?;
-        let game = read_state(|es| {
-            let game_id = action_params_json_value
-                .get("game_id")
-                .map(|v| v.to_string())
-                .ok_or("invalid game id".to_string())?;
-
-            es.games
-                .get(&game_id)
-                .cloned()
-                .ok_or("Game not found".to_string())
-        })?;
+        let ActionParams { game_id, .. } =
+            ActionParams::parse(&action_params).map_err(|e| e.to_string())?;
+        let mut game =
+            get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
+        // the amounts to add depend on how the game ended
+        game.end_if_expired(get_chain_second_timestamp())
+            .map_err(|e| e.to_string())?;
         let (new_state, (_key_derivation_path, _utxo)) = game
             .validate_add_liquidity(
                 pool_address.clone(),
@@ -270,11 +522,24 @@ pub mod exchange {
             )
             .map_err(|e| e.to_string())?;
 
-        mutate_state(|es| {
-            let game_id = game.game_id;
-            let game = es.games.get_mut(&game_id).ok_or("Game not found").unwrap();
-            game.game_status = game.game_status.finish_add_liquidity();
-        });
+        let now = get_chain_second_timestamp();
+        if game.pool_shard(&pool_address).map_err(|e| e.to_string())? > 0 {
+            // fee pools only hand over their register fees, the game moves on with shard 0
+            mutate_game(&game.game_id, |game| {
+                game.fee_shards_with_liquidity.push(pool_address.clone());
+                Ok(())
+            })
+            .map_err(|e| e.to_string())?;
+            return Ok(new_state);
+        }
+        mutate_game(&game.game_id, |game| {
+            game.end_if_expired(now)?;
+            game.game_status = game.game_status.finish_add_liquidity()?;
+            game.add_liquidity_tx = Some(TxProgress::new(args.txid.clone()));
+            Ok(())
+        })
+        .map_err(|e| e.to_string())?;
+        track_tx(args.txid.clone(), TrackedTx::AddLiquidity(game.game_id.clone()));
 
         Ok(new_state)
     }
@@ -292,20 +557,9 @@ pub mod exchange {
             input_coins,
             output_coins,
         } = args.intention;
-        let action_params_json_value =
-            serde_json::from_str::<serde_json::Value>(action_params.as_str())
-                .map_err(|_| "invalid action params".to_string())?;
-        let game = read_state(|es| {
-            let game_id = action_params_json_value
-                .get("game_id")
-                .map(|v| v.to_string())
-                .ok_or("invalid game id".to_string())?;
-
-            es.games
-                .get(&game_id)
-                .cloned()
-                .ok_or("Game not found".to_string())
-        })?;
+        let ActionParams { game_id, .. } =
+            ActionParams::parse(&action_params).map_err(|e| e.to_string())?;
+        let game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
         let (new_state, (_key_derivation_path, _utxo)) = game
             .validate_withdraw(
                 pool_address.clone(),
@@ -319,12 +573,139 @@ pub mod exchange {
             )
             .map_err(|e| e.to_string())?;
 
-        mutate_state(|es| {
-            let game_id = game.game_id;
-            let game = es.games.get_mut(&game_id).ok_or("Game not found").unwrap();
-            game.withdraw(args.initiator_address.clone())
-                .expect("Failed to withdraw");
-        });
+        mutate_game(&game.game_id, |game| {
+            game.withdraw(args.initiator_address.clone())?;
+            game.update_gamer(&args.initiator_address, |gamer| {
+                gamer.withdraw_tx = Some(TxProgress::new(args.txid.clone()))
+            })
+        })
+        .map_err(|e| e.to_string())?;
+        track_tx(
+            args.txid.clone(),
+            TrackedTx::Withdraw(game.game_id.clone(), args.initiator_address.clone()),
+        );
+
+        Ok(new_state)
+    }
+
+    #[action]
+    pub async fn batch_withdraw(
+        _psbt: &bitcoin::Psbt,
+        args: ActionArgs,
+    ) -> ActionResult<CookiePoolState> {
+        let Intention {
+            exchange_id: _,
+            action: _,
+            action_params,
+            pool_address,
+            nonce,
+            pool_utxo_spent,
+            pool_utxo_received,
+            input_coins,
+            output_coins,
+        } = args.intention;
+        let ActionParams { game_id, .. } =
+            ActionParams::parse(&action_params).map_err(|e| e.to_string())?;
+        let game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
+        // every output coin pays one gamer, the validation rejects repeated gamers
+        let gamers: Vec<AddressStr> = output_coins.iter().map(|c| c.to.clone()).collect();
+        let (new_state, (_key_derivation_path, _utxo)) = game
+            .validate_batch_withdraw(
+                pool_address.clone(),
+                args.txid.clone(),
+                nonce,
+                pool_utxo_spent,
+                pool_utxo_received,
+                input_coins,
+                output_coins,
+            )
+            .map_err(|e| e.to_string())?;
+        apply_batch_withdraw(&game.game_id, gamers, args.txid.clone())
+            .map_err(|e| e.to_string())?;
+
+        Ok(new_state)
+    }
+
+    #[action]
+    pub async fn refund(_psbt: &bitcoin::Psbt, args: ActionArgs) -> ActionResult<CookiePoolState> {
+        let Intention {
+            exchange_id: _,
+            action: _,
+            action_params,
+            pool_address,
+            nonce,
+            pool_utxo_spent,
+            pool_utxo_received,
+            input_coins,
+            output_coins,
+        } = args.intention;
+        let ActionParams { game_id, .. } =
+            ActionParams::parse(&action_params).map_err(|e| e.to_string())?;
+        let game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
+        let now = get_chain_second_timestamp();
+        let (new_state, (_key_derivation_path, _utxo)) = game
+            .validate_refund(
+                pool_address.clone(),
+                args.txid.clone(),
+                nonce,
+                pool_utxo_spent,
+                pool_utxo_received,
+                input_coins,
+                output_coins,
+                args.initiator_address.clone(),
+                now,
+            )
+            .map_err(|e| e.to_string())?;
+
+        apply_refund(&game.game_id, args.initiator_address, args.txid, now)
+            .map_err(|e| e.to_string())?;
+
+        Ok(new_state)
+    }
+
+    /// Pays the creator allocation of an ended game out of the pool to the creator.
+    #[action]
+    pub async fn creator_withdraw(
+        _psbt: &bitcoin::Psbt,
+        args: ActionArgs,
+    ) -> ActionResult<CookiePoolState> {
+        let Intention {
+            exchange_id: _,
+            action: _,
+            action_params,
+            pool_address,
+            nonce,
+            pool_utxo_spent,
+            pool_utxo_received,
+            input_coins,
+            output_coins,
+        } = args.intention;
+        let ActionParams { game_id, .. } =
+            ActionParams::parse(&action_params).map_err(|e| e.to_string())?;
+        let game = get_game(&game_id).ok_or(ExchangeError::GameNotFound(game_id).to_string())?;
+        let (new_state, (_key_derivation_path, _utxo)) = game
+            .validate_creator_withdraw(
+                pool_address.clone(),
+                args.txid.clone(),
+                nonce,
+                pool_utxo_spent,
+                pool_utxo_received,
+                input_coins,
+                output_coins,
+                args.initiator_address.clone(),
+            )
+            .map_err(|e| e.to_string())?;
+
+        mutate_game(&game.game_id, |game| {
+            game.withdraw_creator_allocation(&args.initiator_address)?;
+            game.creator_withdraw_tx = Some(TxProgress::new(args.txid.clone()));
+            Ok(())
+        })
+        .map_err(|e| e.to_string())?;
+        track_tx(
+            args.txid.clone(),
+            TrackedTx::CreatorWithdraw(game.game_id.clone()),
+        );
 
         Ok(new_state)
     }
@@ -352,10 +733,1327 @@ pub mod exchange {
                 .expect("Failed to get state")
         })
     }
+
+    pub fn get_game(game_id: &GameId) -> Option<Game> {
+        Games::with(|m| m.get(game_id))
+    }
+
+    pub fn find_game_by_pool_address(pool_address: &AddressStr) -> Option<Game> {
+        let (game_id, _shard) = PoolAddresses::with(|m| m.get(pool_address))?;
+        get_game(&game_id)
+    }
+
+    /// Maps every pool address of `game` to the game and its shard, must be called
+    /// whenever the pools of a game are created.
+    pub fn index_pool_addresses(game: &Game) {
+        PoolAddresses::with_mut(|m| {
+            for (shard, pool_address) in game.pool_addresses().into_iter().enumerate() {
+                m.insert(pool_address, (game.game_id.clone(), shard as u8));
+            }
+        });
+    }
+
+    /// Fills the pool address index from the games created before it existed, returns
+    /// the number of games indexed. Does nothing once any pool address is indexed.
+    pub fn rebuild_pool_address_index() -> usize {
+        if !PoolAddresses::with(|m| m.is_empty()) {
+            return 0;
+        }
+        let games = Games::with(|m| {
+            m.iter()
+                .map(|e| e.value())
+                .filter(|game| game.pool_address.is_some())
+                .collect::<Vec<_>>()
+        });
+        games.iter().for_each(index_pool_addresses);
+        games.len()
+    }
+
+    /// The games `principal` registered in together with its gamer in each of them,
+    /// empty for a principal that never registered.
+    pub fn games_of(principal: &Principal) -> Vec<(Game, Gamer)> {
+        let Some(address) = AddressPrincipalMap::with(|m| m.get(principal)) else {
+            return vec![];
+        };
+        let game_ids = GamerGames::with(|m| {
+            m.range((address.clone(), GameId::new())..)
+                .take_while(|e| e.key().0 == address)
+                .map(|e| e.key().1.clone())
+                .collect::<Vec<_>>()
+        });
+        game_ids
+            .iter()
+            .filter_map(get_game)
+            .filter_map(|game| game.get_gamer(&address).map(|gamer| (game, gamer)))
+            .collect()
+    }
+
+    /// Fills the address index from the gamers stored before it existed, returns the number
+    /// of gamers indexed. Does nothing once any gamer is indexed.
+    pub fn rebuild_gamer_games_index() -> usize {
+        if !GamerGames::with(|m| m.is_empty()) {
+            return 0;
+        }
+        let keys = Gamers::with(|m| m.iter().map(|e| e.key().clone()).collect::<Vec<_>>());
+        GamerGames::with_mut(|m| {
+            for (game_id, address) in keys.iter() {
+                m.insert((address.clone(), game_id.clone()), ());
+            }
+        });
+        keys.len()
+    }
+
+    /// Hands out a game id that was never used before, so that the key path deriving
+    /// the pool address of a game is never reused, even after games are removed.
+    pub fn next_game_id() -> GameId {
+        NextGameId::with_mut(|c| {
+            // older versions used the number of games as id, continue after the highest one
+            let mut id = c.get().unwrap_or_else(|| {
+                Games::with(|m| {
+                    m.iter()
+                        .filter_map(|e| e.key().parse::<u64>().ok())
+                        .max()
+                        .map_or(0, |id| id + 1)
+                })
+            });
+            while Games::with(|m| m.contains_key(&id.to_string())) {
+                id += 1;
+            }
+            c.set(Some(id + 1));
+            id.to_string()
+        })
+    }
+
+    /// Fills the name index from the games created before it existed, returns the number
+    /// of games indexed. Does nothing once any name is indexed.
+    pub fn rebuild_game_name_index() -> usize {
+        if !GameNames::with(|m| m.is_empty()) {
+            return 0;
+        }
+        let names = Games::with(|m| {
+            m.iter()
+                .map(|e| (e.value().game_name.trim().to_string(), e.key().clone()))
+                .collect::<Vec<_>>()
+        });
+        GameNames::with_mut(|m| {
+            for (game_name, game_id) in names.iter() {
+                m.insert(game_name.clone(), game_id.clone());
+            }
+        });
+        names.len()
+    }
+
+    /// Walks the index of the requested order and only loads games until the page is full.
+    pub fn list_games(args: &ListGamesArgs) -> Vec<GameSummary> {
+        match args.sort.unwrap_or_default() {
+            GameSort::Newest => GamesByCreation::with(|m| {
+                page_games(args, m.iter().rev().map(|e| e.key().1.clone()))
+            }),
+            GameSort::Oldest => {
+                GamesByCreation::with(|m| page_games(args, m.iter().map(|e| e.key().1.clone())))
+            }
+            GameSort::MostGamers => {
+                GamesByGamers::with(|m| page_games(args, m.iter().map(|e| e.key().2.clone())))
+            }
+            GameSort::MostClaimed => {
+                GamesByClaimed::with(|m| page_games(args, m.iter().map(|e| e.key().2.clone())))
+            }
+        }
+    }
+
+    fn page_games(
+        args: &ListGamesArgs,
+        game_ids: impl Iterator<Item = GameId>,
+    ) -> Vec<GameSummary> {
+        let limit = args.limit.min(MAX_PAGE_SIZE) as usize;
+        // without filters the offset is skipped on the index, without loading those games
+        if args.status_filter.is_none() && args.creator_filter.is_none() {
+            return game_ids
+                .skip(args.offset as usize)
+                .take(limit)
+                .filter_map(|game_id| get_game(&game_id))
+                .map(|game| GameSummary::from(&game))
+                .collect();
+        }
+        game_ids
+            .filter_map(|game_id| get_game(&game_id))
+            .filter(|game| args.matches(game))
+            .skip(args.offset as usize)
+            .take(limit)
+            .map(|game| GameSummary::from(&game))
+            .collect()
+    }
+
+    fn index_game_order(game: &Game) {
+        let (created, game_id) = (game.creation_order(), game.game_id.clone());
+        let gamers = u64::MAX - game.gamer_count;
+        let claimed = u128::MAX - game.claimed_cookies;
+        GamesByCreation::with_mut(|m| m.insert((created, game_id.clone()), ()));
+        GamesByGamers::with_mut(|m| m.insert((gamers, created, game_id.clone()), ()));
+        GamesByClaimed::with_mut(|m| m.insert((claimed, created, game_id), ()));
+    }
+
+    /// The creation order of a game never changes, only its rankings have to be dropped.
+    fn unindex_game_order(game: &Game) {
+        let (created, game_id) = (game.creation_order(), game.game_id.clone());
+        let gamers = u64::MAX - game.gamer_count;
+        let claimed = u128::MAX - game.claimed_cookies;
+        GamesByGamers::with_mut(|m| m.remove(&(gamers, created, game_id.clone())));
+        GamesByClaimed::with_mut(|m| m.remove(&(claimed, created, game_id)));
+    }
+
+    /// Fills the `list_games` indexes from the games created before they existed, returns
+    /// the number of games indexed. Does nothing once any game is indexed.
+    pub fn rebuild_game_order_index() -> usize {
+        if !GamesByCreation::with(|m| m.is_empty()) {
+            return 0;
+        }
+        let games = Games::with(|m| m.iter().map(|e| e.value()).collect::<Vec<_>>());
+        games.iter().for_each(index_game_order);
+        games.len()
+    }
+
+    /// Writes a game and moves it within the `list_games` rankings.
+    pub fn insert_game(game: Game) {
+        let old = Games::with_mut(|m| m.insert(game.game_id.clone(), game.clone()));
+        if let Some(old) = old {
+            unindex_game_order(&old);
+        }
+        index_game_order(&game);
+    }
+
+    /// Loads a single game, applies `f` and writes the game back only if `f` succeeds.
+    pub fn mutate_game<F, R>(game_id: &GameId, f: F) -> crate::errors::Result<R>
+    where
+        F: FnOnce(&mut Game) -> crate::errors::Result<R>,
+    {
+        let mut game = get_game(game_id).ok_or(ExchangeError::GameNotFound(game_id.clone()))?;
+        let r = f(&mut game)?;
+        insert_game(game);
+        Ok(r)
+    }
+
+    pub fn track_tx(txid: Txid, tracked_tx: TrackedTx) {
+        TrackedTxs::with_mut(|m| {
+            m.insert(txid.to_string(), tracked_tx);
+        });
+    }
+
+    /// Marks every gamer paid by a batch withdraw as withdrawn in one go, and tracks
+    /// the tx so each of them sees it move towards final.
+    pub fn apply_batch_withdraw(
+        game_id: &GameId,
+        gamers: Vec<AddressStr>,
+        txid: Txid,
+    ) -> crate::errors::Result<()> {
+        mutate_game(game_id, |game| {
+            for address in gamers.iter() {
+                game.withdraw(address.clone())?;
+                game.update_gamer(address, |gamer| {
+                    gamer.withdraw_tx = Some(TxProgress::new(txid.clone()))
+                })?;
+            }
+            Ok(())
+        })?;
+        track_tx(txid, TrackedTx::BatchWithdraw(game_id.clone(), gamers));
+        Ok(())
+    }
+
+    /// Marks the register fee of a gamer as paid back, cancelling the game if it was
+    /// abandoned, and tracks the refund tx.
+    pub fn apply_refund(
+        game_id: &GameId,
+        address: AddressStr,
+        txid: Txid,
+        now: crate::SecondTimestamp,
+    ) -> crate::errors::Result<()> {
+        mutate_game(game_id, |game| {
+            game.refund(address.clone(), now)?;
+            game.update_gamer(&address, |gamer| {
+                gamer.refund_tx = Some(TxProgress::new(txid.clone()))
+            })
+        })?;
+        track_tx(txid, TrackedTx::Refund(game_id.clone(), address));
+        Ok(())
+    }
+
+    /// Moves the tx record of the game or gamer behind `txid` forward to `status`,
+    /// and stops tracking the tx once it is final.
+    pub fn advance_tracked_tx(txid: Txid, status: TxStatus, now: crate::SecondTimestamp) {
+        let key = txid.to_string();
+        let Some(tracked_tx) = TrackedTxs::with(|m| m.get(&key)) else {
+            return;
+        };
+        let advance = |tx: &mut Option<TxProgress>| {
+            if let Some(tx) = tx.as_mut().filter(|tx| tx.txid == txid) {
+                tx.advance(status, now);
+            }
+        };
+        let _ = match tracked_tx {
+            TrackedTx::Register(game_id, address) => mutate_game(&game_id, |game| {
+                game.update_gamer(&address, |gamer| advance(&mut gamer.register_tx))?;
+                // paying earlier, a rollback would have to take back cookies already claimed
+                if status == TxStatus::Final {
+                    referral::pay_referral_bonus(game, &address)?;
+                }
+                Ok(())
+            }),
+            TrackedTx::AddLiquidity(game_id) => mutate_game(&game_id, |game| {
+                advance(&mut game.add_liquidity_tx);
+                Ok(())
+            }),
+            TrackedTx::Withdraw(game_id, address) => mutate_game(&game_id, |game| {
+                game.update_gamer(&address, |gamer| advance(&mut gamer.withdraw_tx))
+            }),
+            TrackedTx::BatchWithdraw(game_id, addresses) => mutate_game(&game_id, |game| {
+                for address in addresses.iter() {
+                    game.update_gamer(address, |gamer| advance(&mut gamer.withdraw_tx))?;
+                }
+                Ok(())
+            }),
+            TrackedTx::Refund(game_id, address) => mutate_game(&game_id, |game| {
+                game.update_gamer(&address, |gamer| advance(&mut gamer.refund_tx))
+            }),
+            TrackedTx::CreatorWithdraw(game_id) => mutate_game(&game_id, |game| {
+                advance(&mut game.creator_withdraw_tx);
+                Ok(())
+            }),
+        };
+        if status == TxStatus::Final {
+            TrackedTxs::with_mut(|m| m.remove(&key));
+        }
+    }
+
+    /// Moves games kept inline in the `State` cell by older versions into `Games` and `Gamers`.
+    pub fn migrate_legacy_games(now: SecondTimestamp) -> usize {
+        let legacy_games = mutate_state(|es| std::mem::take(&mut es.games));
+        let migrated = legacy_games.len();
+        for (_, legacy_game) in legacy_games {
+            let (mut game, gamers) = legacy_game.into_game_and_gamers();
+            // legacy games kept no activity time, count them as active from the upgrade on
+            game.last_active_time = now;
+            for gamer in gamers {
+                game.save_gamer(gamer);
+            }
+            insert_game(game);
+        }
+        migrated
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use candid::Principal;
+    use ree_exchange_sdk::types::{CoinBalances, OutputCoin};
+
+    use super::exchange::{
+        advance_tracked_tx, apply_batch_withdraw, apply_refund, find_game_by_pool_address,
+        games_of, get_game, index_pool_addresses, insert_game, list_games, migrate_legacy_games,
+        mutate_game, next_game_id, read_state, rebuild_game_name_index, rebuild_game_order_index,
+        rebuild_gamer_games_index, rebuild_pool_address_index, track_tx, AddressPrincipalMap,
+        CookiePools, GameNames, GamerGames, Games, GamesByClaimed, GamesByCreation,
+        GamesByGamers, PoolAddresses, State, TrackedTxs, __CustomStorageAccess,
+    };
+    use super::*;
+    use crate::game::game::{
+        CreateGameArgs, Game, GameSort, GameStatus, ListGamesArgs, RuneInfo, GAME_ABANDON_TIMEOUT,
+    };
+    use crate::game::gamer::{TxProgress, TxStatus};
+    use crate::game::{leaderboard, referral};
+    use crate::state::ExchangeState;
+
+    const POOL_ADDRESS: &str = "tb1p_rollback_pool";
+
+    fn pool_state(nonce: u64, user_action: UserAction) -> CookiePoolState {
+        CookiePoolState {
+            txid: Txid::zero(),
+            nonce,
+            utxo: Utxo {
+                txid: Txid::zero(),
+                vout: 0,
+                coins: CoinBalances::new(),
+                sats: 0,
+            },
+            user_action,
+        }
+    }
+
+    fn setup_game(game_id: &str, game_status: GameStatus, gamers: &[&str]) -> Game {
+        let mut game = Game::new(
+            CreateGameArgs {
+                game_name: format!("game {}", game_id),
+                gamer_register_fee: 1000,
+                claim_cooling_down: 10,
+                claim_amount_per_click: 10,
+                create_address: "creator".to_string(),
+                rune_premine_amount: 1000,
+                ..Default::default()
+            },
+            Principal::anonymous(),
+            game_id.to_string(),
+        );
+        game.game_status = game_status;
+        game.pool_address = Some(format!("{}_{}", POOL_ADDRESS, game_id));
+        for gamer in gamers {
+            game.register_new_gamer(gamer.to_string()).unwrap();
+        }
+        index_pool_addresses(&game);
+        insert_game(game.clone());
+        game
+    }
+
+    fn rollback(game: &Game, states: Vec<CookiePoolState>) {
+        <CookiePools as Hook>::on_tx_rollbacked(
+            game.pool_address.clone().unwrap(),
+            Txid::zero(),
+            "rollback in test".to_string(),
+            states,
+        );
+    }
+
+    #[test]
+    fn test_rollback_register_removes_gamer() {
+        let game = setup_game("rollback_register", GameStatus::Playing, &["alice", "bob"]);
+
+        rollback(
+            &game,
+            vec![pool_state(
+                2,
+                UserAction::Register(game.game_id.clone(), "bob".to_string()),
+            )],
+        );
+
+        let game = get_game(&game.game_id).unwrap();
+        assert_eq!(game.gamer_count, 1);
+        assert!(game.contains_gamer(&"alice".to_string()));
+        assert!(!game.contains_gamer(&"bob".to_string()));
+    }
+
+    #[test]
+    fn test_rollback_register_removes_referral() {
+        let game = setup_game("rollback_referral", GameStatus::Playing, &["alice"]);
+        let bob = "bob".to_string();
+        referral::record_referral(&game, &bob, &"alice".to_string()).unwrap();
+        mutate_game(&game.game_id, |game| game.register_new_gamer(bob.clone())).unwrap();
+
+        rollback(
+            &game,
+            vec![pool_state(
+                2,
+                UserAction::Register(game.game_id.clone(), bob.clone()),
+            )],
+        );
+
+        assert!(referral::get_referral(&game.game_id, &bob).is_none());
+        // the referee may register again, referred by someone else
+        assert!(referral::validate_referral(&game, &bob, &"alice".to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_rollback_register_uncounts_fee_shard() {
+        let mut game = setup_game("rollback_shard", GameStatus::Playing, &["alice"]);
+        let fee_pool = format!("{}_rollback_shard_1", POOL_ADDRESS);
+        game.pool_shards = 2;
+        game.fee_pool_addresses = vec![fee_pool.clone()];
+        index_pool_addresses(&game);
+        let bob = "bob".to_string();
+        game.register_new_gamer(bob.clone()).unwrap();
+        game.assign_pool_shard(&bob, 1).unwrap();
+        insert_game(game.clone());
+        assert_eq!(game.shard_gamer_count(1), 1);
+
+        <CookiePools as Hook>::on_tx_rollbacked(
+            fee_pool,
+            Txid::zero(),
+            "rollback in test".to_string(),
+            vec![pool_state(
+                1,
+                UserAction::Register(game.game_id.clone(), bob.clone()),
+            )],
+        );
+
+        let game = get_game(&game.game_id).unwrap();
+        assert_eq!(game.shard_gamer_count(0), 1);
+        assert_eq!(game.shard_gamer_count(1), 0);
+    }
+
+    #[test]
+    fn test_rollback_withdraw_resets_withdrawn_flag() {
+        let game = setup_game("rollback_withdraw", GameStatus::Withdrawing, &["alice"]);
+        let mut alice = game.get_gamer(&"alice".to_string()).unwrap();
+        alice.is_withdrawn = true;
+        game.save_gamer(alice);
+
+        rollback(
+            &game,
+            vec![pool_state(
+                3,
+                UserAction::Withdraw(game.game_id.clone(), "alice".to_string()),
+            )],
+        );
+
+        let alice = game.get_gamer(&"alice".to_string()).unwrap();
+        assert!(!alice.is_withdrawn);
+    }
+
+    #[test]
+    fn test_rollback_add_liquidity_waits_for_liquidity_again() {
+        let game = setup_game("rollback_add_liquidity", GameStatus::Withdrawing, &["alice"]);
+        let mut alice = game.get_gamer(&"alice".to_string()).unwrap();
+        alice.is_withdrawn = true;
+        game.save_gamer(alice);
+
+        rollback(
+            &game,
+            vec![
+                pool_state(3, UserAction::AddLiquidity),
+                pool_state(
+                    4,
+                    UserAction::Withdraw(game.game_id.clone(), "alice".to_string()),
+                ),
+            ],
+        );
+
+        let game = get_game(&game.game_id).unwrap();
+        assert!(matches!(game.game_status, GameStatus::WaitAddedLiquidity));
+        assert!(!game.get_gamer(&"alice".to_string()).unwrap().is_withdrawn);
+    }
+
+    #[test]
+    fn test_rollback_fee_shard_add_liquidity_keeps_game_status() {
+        let mut game = setup_game("rollback_fee_shard", GameStatus::Withdrawing, &["alice"]);
+        let fee_pool = format!("{}_rollback_fee_shard_1", POOL_ADDRESS);
+        game.pool_shards = 2;
+        game.fee_pool_addresses = vec![fee_pool.clone()];
+        game.fee_shards_with_liquidity = vec![fee_pool.clone()];
+        index_pool_addresses(&game);
+        insert_game(game.clone());
+
+        <CookiePools as Hook>::on_tx_rollbacked(
+            fee_pool,
+            Txid::zero(),
+            "rollback in test".to_string(),
+            vec![pool_state(2, UserAction::AddLiquidity)],
+        );
+
+        let game = get_game(&game.game_id).unwrap();
+        assert!(matches!(game.game_status, GameStatus::Withdrawing));
+        assert!(game.fee_shards_with_liquidity.is_empty());
+    }
+
+    #[test]
+    fn test_pool_state_coin_balance() {
+        let mut state = pool_state(0, UserAction::Init);
+        let rune_id: CoinId = "840000:1".parse().unwrap();
+        state.utxo.coins.add_coin(&CoinBalance {
+            id: rune_id.clone(),
+            value: 500,
+        });
+
+        assert_eq!(state.coin_balance(&rune_id), 500);
+        assert_eq!(state.coin_balance(&CoinId::btc()), 0);
+    }
+
+    #[test]
+    fn test_rollback_batch_withdraw_resets_all_gamers() {
+        let game = setup_game("rollback_batch", GameStatus::Withdrawing, &["alice", "bob"]);
+        for address in ["alice", "bob"] {
+            game.update_gamer(&address.to_string(), |gamer| gamer.is_withdrawn = true)
+                .unwrap();
+        }
+
+        rollback(
+            &game,
+            vec![pool_state(
+                3,
+                UserAction::BatchWithdraw(
+                    game.game_id.clone(),
+                    vec!["alice".to_string(), "bob".to_string()],
+                ),
+            )],
+        );
+
+        let game = get_game(&game.game_id).unwrap();
+        assert!(game.gamers().iter().all(|gamer| !gamer.is_withdrawn));
+    }
+
+    #[test]
+    fn test_batch_withdraw_marks_every_gamer_until_final() {
+        let game = setup_game(
+            "apply_batch_withdraw",
+            GameStatus::Withdrawing,
+            &["alice", "bob", "carol"],
+        );
+        let gamers = vec!["alice".to_string(), "bob".to_string()];
+        apply_batch_withdraw(&game.game_id, gamers.clone(), Txid::zero()).unwrap();
+
+        let game = get_game(&game.game_id).unwrap();
+        for address in gamers.iter() {
+            let gamer = game.get_gamer(address).unwrap();
+            assert!(gamer.is_withdrawn);
+            assert_eq!(gamer.withdraw_tx.unwrap().status, TxStatus::Pending);
+        }
+        let carol = game.get_gamer(&"carol".to_string()).unwrap();
+        assert!(!carol.is_withdrawn && carol.withdraw_tx.is_none());
+
+        advance_tracked_tx(Txid::zero(), TxStatus::Final, 200);
+        let game = get_game(&game.game_id).unwrap();
+        for address in gamers.iter() {
+            let withdraw_tx = game.get_gamer(address).unwrap().withdraw_tx.unwrap();
+            assert_eq!(withdraw_tx.status, TxStatus::Final);
+            assert_eq!(withdraw_tx.finalized_at, Some(200));
+        }
+        let key = Txid::zero().to_string();
+        assert!(!TrackedTxs::with(|m| m.contains_key(&key)));
+
+        // a gamer already paid makes the whole batch fail
+        assert!(matches!(
+            apply_batch_withdraw(&game.game_id, gamers, Txid::zero()),
+            Err(ExchangeError::GamerWithdrawRepeatedly(_))
+        ));
+    }
+
+    #[test]
+    fn test_batch_withdraw_checks_every_output() {
+        let mut game = setup_game(
+            "batch_withdraw",
+            GameStatus::Withdrawing,
+            &["alice", "bob", "carol"],
+        );
+        let rune_id: CoinId = "840000:2".parse().unwrap();
+        game.rune_info = Some(RuneInfo {
+            rune_id: rune_id.clone(),
+            rune_name: "BATCH".to_string(),
+        });
+        game.update_gamer(&"alice".to_string(), |gamer| gamer.cookies = 30)
+            .unwrap();
+        game.update_gamer(&"bob".to_string(), |gamer| gamer.cookies = 20)
+            .unwrap();
+        game.update_gamer(&"carol".to_string(), |gamer| gamer.is_withdrawn = true)
+            .unwrap();
+        let output = |to: &str, value: u128| OutputCoin {
+            to: to.to_string(),
+            coin: CoinBalance {
+                id: rune_id.clone(),
+                value,
+            },
+        };
+        let validate = |output_coins: Vec<OutputCoin>| {
+            game.validate_batch_withdraw(
+                game.pool_address.clone().unwrap(),
+                Txid::zero(),
+                0,
+                vec![],
+                vec![],
+                vec![],
+                output_coins,
+            )
+        };
+
+        assert!(matches!(
+            validate(vec![]),
+            Err(ExchangeError::InvalidWithdrawCoins(_))
+        ));
+        assert!(matches!(
+            validate(vec![output("alice", 30), output("alice", 30)]),
+            Err(ExchangeError::GamerWithdrawRepeatedly(_))
+        ));
+        assert!(matches!(
+            validate(vec![output("alice", 30), output("carol", 0)]),
+            Err(ExchangeError::GamerWithdrawRepeatedly(_))
+        ));
+        assert!(matches!(
+            validate(vec![output("alice", 30), output("dave", 10)]),
+            Err(ExchangeError::GamerNotFound(_))
+        ));
+        assert!(matches!(
+            validate(vec![output("alice", 30), output("bob", 25)]),
+            Err(ExchangeError::InvalidWithdrawCoins(_))
+        ));
+        // the outputs are fine, only the test has no pool to spend from
+        assert!(matches!(
+            validate(vec![output("alice", 30), output("bob", 20)]),
+            Err(ExchangeError::PoolNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_find_game_by_any_shard_address() {
+        let mut game = setup_game("pool_index", GameStatus::Playing, &[]);
+        game.pool_shards = 2;
+        game.fee_pool_addresses = vec!["fee_1".to_string()];
+        index_pool_addresses(&game);
+        insert_game(game.clone());
+
+        for pool_address in game.pool_addresses() {
+            let found = find_game_by_pool_address(&pool_address).unwrap();
+            assert_eq!(found.game_id, game.game_id);
+        }
+        assert_eq!(
+            PoolAddresses::with(|m| m.get(&"fee_1".to_string())),
+            Some((game.game_id.clone(), 1))
+        );
+        assert!(find_game_by_pool_address(&"other_pool".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_rebuild_pool_address_index_runs_once() {
+        let game = setup_game("1", GameStatus::Playing, &[]);
+        let mut etching = setup_game("2", GameStatus::Etching, &[]);
+        etching.pool_address = None;
+        insert_game(etching);
+        PoolAddresses::with_mut(|m| {
+            for game_id in ["1", "2"] {
+                m.remove(&format!("{}_{}", POOL_ADDRESS, game_id));
+            }
+        });
+
+        assert_eq!(rebuild_pool_address_index(), 1);
+        let found = find_game_by_pool_address(game.pool_address.as_ref().unwrap());
+        assert_eq!(found.unwrap().game_id, "1");
+        assert_eq!(rebuild_pool_address_index(), 0);
+    }
+
+    #[test]
+    fn test_refund_of_cancelled_game() {
+        let game = setup_game("refund_cancelled", GameStatus::Cancelled, &["alice", "bob"]);
+        let alice = "alice".to_string();
+        apply_refund(&game.game_id, alice.clone(), Txid::zero(), 100).unwrap();
+
+        let game = get_game(&game.game_id).unwrap();
+        let gamer = game.get_gamer(&alice).unwrap();
+        assert!(gamer.is_refunded);
+        assert_eq!(gamer.refund_tx.unwrap().status, TxStatus::Pending);
+        assert!(!game.get_gamer(&"bob".to_string()).unwrap().is_refunded);
+        let key = Txid::zero().to_string();
+        assert!(TrackedTxs::with(|m| m.contains_key(&key)));
+    }
+
+    #[test]
+    fn test_refund_of_abandoned_game_cancels_it() {
+        let game = setup_game("refund_abandoned", GameStatus::Playing, &["alice", "bob"]);
+        let alice = "alice".to_string();
+        let abandoned_at = GAME_ABANDON_TIMEOUT;
+        // nobody was active for a whole timeout yet
+        assert!(matches!(
+            apply_refund(&game.game_id, alice.clone(), Txid::zero(), abandoned_at - 1),
+            Err(ExchangeError::GameNotRefundable(_))
+        ));
+
+        apply_refund(&game.game_id, alice.clone(), Txid::zero(), abandoned_at).unwrap();
+
+        let game = get_game(&game.game_id).unwrap();
+        assert!(matches!(game.game_status, GameStatus::Cancelled));
+        assert!(game.get_gamer(&alice).unwrap().is_refunded);
+        // the game stays refundable for the other gamers
+        assert!(game.is_refundable(abandoned_at));
+    }
+
+    #[test]
+    fn test_refund_is_rejected_twice() {
+        let game = setup_game("refund_twice", GameStatus::Cancelled, &["alice"]);
+        let alice = "alice".to_string();
+        apply_refund(&game.game_id, alice.clone(), Txid::zero(), 100).unwrap();
+
+        assert!(matches!(
+            apply_refund(&game.game_id, alice.clone(), Txid::zero(), 100),
+            Err(ExchangeError::GamerRefundRepeatedly(_))
+        ));
+        let game = get_game(&game.game_id).unwrap();
+        assert!(matches!(
+            game.validate_refund(
+                game.pool_address.clone().unwrap(),
+                Txid::zero(),
+                0,
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                alice,
+                100,
+            ),
+            Err(ExchangeError::GamerRefundRepeatedly(_))
+        ));
+    }
+
+    #[test]
+    fn test_refund_comes_from_the_shard_of_the_gamer() {
+        let mut game = setup_game("refund_shard", GameStatus::Cancelled, &["alice"]);
+        let alice = "alice".to_string();
+        game.pool_shards = 2;
+        game.fee_pool_addresses = vec!["fee_1".to_string()];
+        game.assign_pool_shard(&alice, 1).unwrap();
+        let refund = |pool_address: String| {
+            game.validate_refund(
+                pool_address,
+                Txid::zero(),
+                0,
+                vec![],
+                vec![],
+                vec![],
+                vec![OutputCoin {
+                    to: alice.clone(),
+                    coin: CoinBalance {
+                        id: CoinId::btc(),
+                        value: 1000,
+                    },
+                }],
+                alice.clone(),
+                100,
+            )
+        };
+
+        assert!(matches!(
+            refund(game.pool_address.clone().unwrap()),
+            Err(ExchangeError::PoolAddressMismatch { .. })
+        ));
+        // the shard of alice is right, there is just no pool behind it in this test
+        assert!(matches!(
+            refund("fee_1".to_string()),
+            Err(ExchangeError::PoolNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_only_the_rune_shard_pays_out_runes() {
+        let mut game = setup_game("rune_shard", GameStatus::Withdrawing, &["alice"]);
+        game.pool_shards = 2;
+        game.fee_pool_addresses = vec!["fee_1".to_string()];
+        let rune_id: CoinId = "840000:3".parse().unwrap();
+        game.rune_info = Some(RuneInfo {
+            rune_id: rune_id.clone(),
+            rune_name: "SHARD".to_string(),
+        });
+        // alice has no cookies, the payout itself is valid
+        let payout = vec![OutputCoin {
+            to: "alice".to_string(),
+            coin: CoinBalance {
+                id: rune_id,
+                value: 0,
+            },
+        }];
+
+        assert!(matches!(
+            game.validate_withdraw(
+                "fee_1".to_string(),
+                Txid::zero(),
+                0,
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                "alice".to_string(),
+            ),
+            Err(ExchangeError::PoolAddressMismatch { .. })
+        ));
+        assert!(matches!(
+            game.validate_withdraw(
+                game.pool_address.clone().unwrap(),
+                Txid::zero(),
+                0,
+                vec![],
+                vec![],
+                vec![],
+                payout,
+                "alice".to_string(),
+            ),
+            Err(ExchangeError::PoolNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_rollback_ignores_unknown_game() {
+        let game = setup_game("rollback_unknown", GameStatus::Playing, &["alice"]);
+
+        rollback(
+            &game,
+            vec![
+                pool_state(0, UserAction::Init),
+                pool_state(
+                    1,
+                    UserAction::Register("missing".to_string(), "alice".to_string()),
+                ),
+            ],
+        );
+
+        let game = get_game(&game.game_id).unwrap();
+        assert_eq!(game.gamer_count, 1);
+        assert!(matches!(game.game_status, GameStatus::Playing));
+    }
+
+    #[test]
+    fn test_rollback_creator_withdraw_resets_withdrawn_flag() {
+        let mut game = setup_game("rollback_creator_withdraw", GameStatus::Withdrawing, &[]);
+        game.creator_share_bps = 500;
+        game.withdraw_creator_allocation(&"creator".to_string())
+            .unwrap();
+        insert_game(game.clone());
+
+        rollback(
+            &game,
+            vec![pool_state(
+                3,
+                UserAction::CreatorWithdraw(game.game_id.clone()),
+            )],
+        );
+
+        let game = get_game(&game.game_id).unwrap();
+        assert!(!game.is_creator_allocation_withdrawn);
+        assert!(game.creator_withdraw_tx.is_none());
+    }
+
+    #[test]
+    fn test_tracked_register_tx_advances_until_final() {
+        let game = setup_game("tracked_register", GameStatus::Playing, &["alice"]);
+        let alice = "alice".to_string();
+        game.update_gamer(&alice, |gamer| {
+            gamer.register_tx = Some(TxProgress::new(Txid::zero()))
+        })
+        .unwrap();
+        track_tx(
+            Txid::zero(),
+            TrackedTx::Register(game.game_id.clone(), alice.clone()),
+        );
+        let register_tx = || game.get_gamer(&alice).unwrap().register_tx.unwrap();
+        let is_tracked = || TrackedTxs::with(|m| m.contains_key(&Txid::zero().to_string()));
+
+        advance_tracked_tx(Txid::zero(), TxStatus::Confirmed, 100);
+        assert_eq!(register_tx().status, TxStatus::Confirmed);
+        assert_eq!(register_tx().finalized_at, None);
+        assert!(is_tracked());
+
+        // a late pending notification never moves the tx back
+        advance_tracked_tx(Txid::zero(), TxStatus::Pending, 150);
+        assert_eq!(register_tx().status, TxStatus::Confirmed);
+
+        advance_tracked_tx(Txid::zero(), TxStatus::Final, 200);
+        assert_eq!(register_tx().status, TxStatus::Final);
+        assert_eq!(register_tx().finalized_at, Some(200));
+        assert!(!is_tracked());
+
+        // once untracked, further notifications are ignored
+        advance_tracked_tx(Txid::zero(), TxStatus::Final, 300);
+        assert_eq!(register_tx().finalized_at, Some(200));
+    }
+
+    #[test]
+    fn test_tracked_add_liquidity_tx_advances_game() {
+        let mut game = setup_game("tracked_add_liquidity", GameStatus::Playing, &[]);
+        game.add_liquidity_tx = Some(TxProgress::new(Txid::zero()));
+        insert_game(game.clone());
+        track_tx(Txid::zero(), TrackedTx::AddLiquidity(game.game_id.clone()));
+
+        advance_tracked_tx(Txid::zero(), TxStatus::Confirmed, 100);
+        let add_liquidity_tx = get_game(&game.game_id).unwrap().add_liquidity_tx.unwrap();
+        assert_eq!(add_liquidity_tx.status, TxStatus::Confirmed);
+
+        advance_tracked_tx(Txid::zero(), TxStatus::Final, 200);
+        let add_liquidity_tx = get_game(&game.game_id).unwrap().add_liquidity_tx.unwrap();
+        assert_eq!(add_liquidity_tx.status, TxStatus::Final);
+        assert_eq!(add_liquidity_tx.finalized_at, Some(200));
+    }
+
+    #[test]
+    fn test_next_game_id_never_reuses_ids() {
+        let first = next_game_id();
+        let game = setup_game(&first, GameStatus::Playing, &[]);
+        let second = next_game_id();
+        assert_ne!(first, second);
+
+        Games::with_mut(|m| m.remove(&game.game_id));
+        let third = next_game_id();
+        assert_ne!(third, first);
+        assert_ne!(third, second);
+        assert!(third.parse::<u64>().unwrap() > second.parse::<u64>().unwrap());
+    }
+
+    #[test]
+    fn test_games_of_walks_the_address_index() {
+        let alice = "alice".to_string();
+        setup_game("1", GameStatus::Playing, &["alice", "bob"]);
+        setup_game("2", GameStatus::Playing, &["bob"]);
+        let mut third = setup_game("3", GameStatus::Playing, &["alice"]);
+        let principal = Principal::anonymous();
+        let game_ids = || {
+            games_of(&principal)
+                .into_iter()
+                .map(|(game, gamer)| {
+                    assert_eq!(gamer.address, alice);
+                    game.game_id
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // a caller that never registered has no games rather than an error
+        assert!(game_ids().is_empty());
+
+        AddressPrincipalMap::with_mut(|m| m.insert(principal, alice.clone()));
+        assert_eq!(game_ids(), vec!["1", "3"]);
+
+        third.remove_gamer(&alice);
+        insert_game(third);
+        assert_eq!(game_ids(), vec!["1"]);
+    }
+
+    #[test]
+    fn test_rebuild_gamer_games_index_runs_once() {
+        setup_game("1", GameStatus::Playing, &["alice", "bob"]);
+        setup_game("2", GameStatus::Playing, &["bob"]);
+        GamerGames::with_mut(|m| {
+            for key in [("alice", "1"), ("bob", "1"), ("bob", "2")] {
+                m.remove(&(key.0.to_string(), key.1.to_string()));
+            }
+        });
+
+        assert_eq!(rebuild_gamer_games_index(), 3);
+        let key = ("bob".to_string(), "2".to_string());
+        assert!(GamerGames::with(|m| m.contains_key(&key)));
+        assert_eq!(rebuild_gamer_games_index(), 0);
+    }
+
+    #[test]
+    fn test_rebuild_game_name_index_runs_once() {
+        setup_game("1", GameStatus::Playing, &[]);
+        setup_game("2", GameStatus::Playing, &[]);
+
+        assert_eq!(rebuild_game_name_index(), 2);
+        assert_eq!(
+            GameNames::with(|m| m.get(&"game 2".to_string())),
+            Some("2".to_string())
+        );
+
+        setup_game("3", GameStatus::Playing, &[]);
+        assert_eq!(rebuild_game_name_index(), 0);
+        assert!(!GameNames::with(|m| m.contains_key(&"game 3".to_string())));
+    }
+
+    /// The state cell layout before games and gamers moved into their own maps.
+    #[derive(Serialize)]
+    struct BaselineState {
+        games: HashMap<GameId, BaselineGame>,
+        txid_game_map: HashMap<String, GameId>,
+    }
+
+    #[derive(Serialize)]
+    struct BaselineGame {
+        game_id: GameId,
+        game_name: String,
+        gamer_register_fee: u64,
+        claim_cooling_down: u64,
+        claim_amount_per_click: u128,
+        game_status: GameStatus,
+        creator: Principal,
+        creator_address: AddressStr,
+        pool_address: Option<AddressStr>,
+        rune_premine_amount: u128,
+        rune_info: Option<RuneInfo>,
+        claimed_cookies: u128,
+        gamers: HashMap<AddressStr, BaselineGamer>,
+        etch_rune_commit_tx: String,
+    }
+
+    #[derive(Serialize)]
+    struct BaselineGamer {
+        address: String,
+        cookies: u128,
+        last_click_time: u64,
+        is_withdrawn: bool,
+    }
+
+    fn baseline_game(game_id: &str, gamers: &[(&str, u128)]) -> BaselineGame {
+        BaselineGame {
+            game_id: game_id.to_string(),
+            game_name: format!("game {}", game_id),
+            gamer_register_fee: 1000,
+            claim_cooling_down: 10,
+            claim_amount_per_click: 10,
+            game_status: GameStatus::Playing,
+            creator: Principal::anonymous(),
+            creator_address: "creator".to_string(),
+            pool_address: Some(format!("{}_{}", POOL_ADDRESS, game_id)),
+            rune_premine_amount: 1000,
+            rune_info: None,
+            claimed_cookies: gamers.iter().map(|(_, cookies)| cookies).sum(),
+            gamers: gamers
+                .iter()
+                .map(|(address, cookies)| {
+                    let gamer = BaselineGamer {
+                        address: address.to_string(),
+                        cookies: *cookies,
+                        last_click_time: 50,
+                        is_withdrawn: false,
+                    };
+                    (address.to_string(), gamer)
+                })
+                .collect(),
+            etch_rune_commit_tx: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_migrate_legacy_games() {
+        let baseline = BaselineState {
+            games: [
+                ("1", baseline_game("1", &[("alice", 30), ("bob", 10)])),
+                ("2", baseline_game("2", &[("bob", 20)])),
+            ]
+            .into_iter()
+            .map(|(game_id, game)| (game_id.to_string(), game))
+            .collect(),
+            txid_game_map: [("commit_tx".to_string(), "1".to_string())].into(),
+        };
+        let bytes = bincode::serialize(&baseline).unwrap();
+        State::with_mut(|s| {
+            s.set(Some(ExchangeState::from_bytes(Cow::Owned(bytes))));
+        });
+
+        assert_eq!(migrate_legacy_games(100), 2);
+        assert!(read_state(|es| es.games.is_empty()));
+        assert_eq!(read_state(|es| es.txid_game_map.len()), 1);
+
+        let game = get_game(&"1".to_string()).unwrap();
+        assert_eq!(game.gamer_count, 2);
+        assert_eq!(game.claimed_cookies, 40);
+        assert_eq!(game.last_active_time, 100);
+        assert!(matches!(game.game_status, GameStatus::Playing));
+        let alice = game.get_gamer(&"alice".to_string()).unwrap();
+        assert_eq!(alice.cookies, 30);
+        assert_eq!(alice.last_click_time, 50);
+        assert_eq!(get_game(&"2".to_string()).unwrap().gamer_count, 1);
+
+        // post_upgrade rebuilds the indexes of the migrated games
+        assert_eq!(rebuild_pool_address_index(), 2);
+        assert_eq!(rebuild_game_name_index(), 2);
+        assert_eq!(rebuild_gamer_games_index(), 3);
+        assert_eq!(leaderboard::rebuild_leaderboards(), 3);
+        assert_eq!(
+            find_game_by_pool_address(&format!("{}_2", POOL_ADDRESS)).map(|g| g.game_id),
+            Some("2".to_string())
+        );
+        assert_eq!(
+            GameNames::with(|m| m.get(&"game 1".to_string())),
+            Some("1".to_string())
+        );
+        let key = ("bob".to_string(), "2".to_string());
+        assert!(GamerGames::with(|m| m.contains_key(&key)));
+        let ranked = leaderboard::game_leaderboard(&"1".to_string(), 10)
+            .into_iter()
+            .map(|e| (e.address, e.cookies))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranked,
+            vec![("alice".to_string(), 30), ("bob".to_string(), 10)]
+        );
+        let newest = list_games(&ListGamesArgs {
+            limit: 10,
+            ..Default::default()
+        });
+        let game_ids = newest.into_iter().map(|g| g.game_id).collect::<Vec<_>>();
+        assert_eq!(game_ids, vec!["2", "1"]);
+    }
+
+    #[test]
+    fn test_list_games_filters_sorts_and_pages() {
+        setup_game("8", GameStatus::Playing, &["alice"]);
+        setup_game("9", GameStatus::Paused, &[]);
+        setup_game("10", GameStatus::Playing, &["alice", "bob", "carol"]);
+        setup_game("11", GameStatus::EtchingFailed("boom".to_string()), &[]);
+
+        let ids = |args: ListGamesArgs| {
+            list_games(&args)
+                .into_iter()
+                .map(|g| g.game_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(ListGamesArgs {
+                limit: 10,
+                ..Default::default()
+            }),
+            vec!["11", "10", "9", "8"]
+        );
+        assert_eq!(
+            ids(ListGamesArgs {
+                offset: 1,
+                limit: 2,
+                sort: Some(GameSort::Oldest),
+                ..Default::default()
+            }),
+            vec!["9", "10"]
+        );
+        assert_eq!(
+            ids(ListGamesArgs {
+                limit: 10,
+                status_filter: Some(GameStatus::Playing),
+                sort: Some(GameSort::MostGamers),
+                ..Default::default()
+            }),
+            vec!["10", "8"]
+        );
+        assert_eq!(
+            ids(ListGamesArgs {
+                limit: 10,
+                status_filter: Some(GameStatus::EtchingFailed(String::new())),
+                ..Default::default()
+            }),
+            vec!["11"]
+        );
+        assert!(ids(ListGamesArgs {
+            limit: 10,
+            creator_filter: Some(Principal::management_canister()),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_list_games_follows_game_updates() {
+        setup_game("1", GameStatus::Playing, &["alice"]);
+        setup_game("2", GameStatus::Playing, &[]);
+        setup_game("3", GameStatus::Playing, &[]);
+        mutate_game(&"3".to_string(), |game| {
+            game.register_new_gamer("alice".to_string())?;
+            game.register_new_gamer("bob".to_string())?;
+            game.claimed_cookies = 10;
+            Ok(())
+        })
+        .unwrap();
+        mutate_game(&"2".to_string(), |game| {
+            game.claimed_cookies = 20;
+            Ok(())
+        })
+        .unwrap();
+
+        let ids = |sort| {
+            list_games(&ListGamesArgs {
+                limit: 10,
+                sort: Some(sort),
+                ..Default::default()
+            })
+            .into_iter()
+            .map(|g| g.game_id)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(GameSort::MostGamers), vec!["3", "1", "2"]);
+        assert_eq!(ids(GameSort::MostClaimed), vec!["2", "3", "1"]);
+        // every game keeps a single entry in each index
+        assert_eq!(GamesByCreation::with(|m| m.len()), 3);
+        assert_eq!(GamesByGamers::with(|m| m.len()), 3);
+        assert_eq!(GamesByClaimed::with(|m| m.len()), 3);
+    }
+
+    #[test]
+    fn test_rebuild_game_order_index() {
+        // games written by older versions, before the indexes existed
+        for game_id in ["9", "10"] {
+            let game = Game::new(
+                CreateGameArgs {
+                    game_name: format!("game {}", game_id),
+                    ..Default::default()
+                },
+                Principal::anonymous(),
+                game_id.to_string(),
+            );
+            Games::with_mut(|m| m.insert(game_id.to_string(), game));
+        }
+
+        assert_eq!(rebuild_game_order_index(), 2);
+        let ids = list_games(&ListGamesArgs {
+            limit: 10,
+            sort: Some(GameSort::Oldest),
+            ..Default::default()
+        })
+        .into_iter()
+        .map(|g| g.game_id)
+        .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["9", "10"]);
+        assert_eq!(GamesByGamers::with(|m| m.len()), 2);
+        assert_eq!(rebuild_game_order_index(), 0);
+    }
+
+    #[test]
+    fn test_gamers_page_stays_within_game() {
+        let game = setup_game("20", GameStatus::Playing, &["alice", "bob", "carol"]);
+        setup_game("21", GameStatus::Playing, &["dave"]);
+
+        let page = |offset, limit| {
+            game.gamers_page(offset, limit)
+                .into_iter()
+                .map(|g| g.address)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(page(0, 2), vec!["alice", "bob"]);
+        assert_eq!(page(2, 2), vec!["carol"]);
+        assert!(page(3, 2).is_empty());
+    }
+
+    #[test]
+    fn test_action_params_string_game_id() {
+        let params = ActionParams::parse(r#"{"game_id":"0"}"#).unwrap();
+        assert_eq!(params.game_id, "0");
+        assert!(params.extra.is_empty());
+    }
+
+    #[test]
+    fn test_action_params_numeric_game_id() {
+        let params = ActionParams::parse(r#"{"game_id":12}"#).unwrap();
+        assert_eq!(params.game_id, "12");
+    }
+
+    #[test]
+    fn test_action_params_keeps_extra_fields() {
+        let params = ActionParams::parse(r#"{"game_id":"3","referrer":"tb1qref"}"#).unwrap();
+        assert_eq!(params.game_id, "3");
+        assert_eq!(
+            params.extra.get("referrer").and_then(|v| v.as_str()),
+            Some("tb1qref")
+        );
+        assert_eq!(params.referrer().unwrap(), Some("tb1qref".to_string()));
+    }
+
+    #[test]
+    fn test_action_params_referrer_must_be_an_address() {
+        let params = ActionParams::parse(r#"{"game_id":"3"}"#).unwrap();
+        assert_eq!(params.referrer().unwrap(), None);
+        for params in [
+            r#"{"game_id":"3","referrer":""}"#,
+            r#"{"game_id":"3","referrer":12}"#,
+        ] {
+            assert!(matches!(
+                ActionParams::parse(params).unwrap().referrer(),
+                Err(ExchangeError::InvalidActionParams(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_action_params_rejects_malformed_params() {
+        for params in [
+            "",
+            "not json",
+            "{}",
+            r#"{"game":"0"}"#,
+            r#"{"game_id":""}"#,
+            r#"{"game_id":-1}"#,
+            r#"{"game_id":1.5}"#,
+            r#"{"game_id":true}"#,
+            r#"{"game_id":null}"#,
+        ] {
+            assert!(
+                matches!(
+                    ActionParams::parse(params),
+                    Err(ExchangeError::InvalidActionParams(_))
+                ),
+                "{} should be rejected",
+                params
+            );
+        }
+    }
+}
 
 #[test]
 pub fn test() {
     let empty_txid = Txid::zero();
-}
\ No newline at end of file
+}
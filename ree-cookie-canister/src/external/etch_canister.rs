@@ -5,7 +5,7 @@ use icrc_ledger_types::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{ETCH_CANISTER_ID, ICP_LEDGER_CANISTER_ID};
+use crate::config::read_config;
 
 #[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct LogoParams {
@@ -56,7 +56,7 @@ pub enum EtchingStatus {
 }
 
 pub async fn get_etching_request(commit_tx: String) -> Option<SendEtchingInfo> {
-    let etching_principal = Principal::from_text(ETCH_CANISTER_ID).ok()?;
+    let etching_principal = read_config(|c| c.etch_canister);
     let (info,): (Option<SendEtchingInfo>,) =
         ic_cdk::api::call::call(etching_principal, "get_etching_request", (commit_tx,))
             .await
@@ -65,8 +65,8 @@ pub async fn get_etching_request(commit_tx: String) -> Option<SendEtchingInfo> {
 }
 
 pub async fn etching(args: EtchingArgs) -> Result<String, String> {
-    let etching_principal = Principal::from_text(ETCH_CANISTER_ID)
-        .map_err(|e| format!("Invalid etching canister ID: {}", e))?;
+    let (etching_principal, icp_ledger_principal) =
+        read_config(|c| (c.etch_canister, c.icp_ledger_canister));
 
     let approve_args = ApproveArgs {
         from_subaccount: None,
@@ -82,7 +82,7 @@ pub async fn etching(args: EtchingArgs) -> Result<String, String> {
         created_at_time: None,
     };
     let result: (Result<Nat, ApproveError>,) = ic_cdk::api::call::call(
-        Principal::from_text(ICP_LEDGER_CANISTER_ID).unwrap(),
+        icp_ledger_principal,
         "icrc2_approve",
         (approve_args,),
     )
@@ -2,11 +2,10 @@ use candid::Principal;
 use ic_cdk::api::call::CallResult;
 use serde_bytes::ByteBuf;
 
-use crate::SIWB_TESTNET_CANISTER;
+use crate::config::read_config;
 
 pub async fn get_principal(address: String) -> Result<Principal, String> {
-    let siwb_principal = Principal::from_text(SIWB_TESTNET_CANISTER)
-        .map_err(|e| format!("Failed to parse SIWB_TESTNET_CANISTER principal: {}", e))?;
+    let siwb_principal = read_config(|c| c.siwb_canister);
     let result: CallResult<(Result<ByteBuf, String>,)> =
         ic_cdk::call(siwb_principal, "get_principal", (address,)).await;
     let success_result = result
@@ -2,7 +2,7 @@
 use candid::{self, CandidType, Decode, Deserialize, Encode, Principal};
 use ic_cdk::api::call::CallResult as Result;
 
-use crate::{ETCH_CANISTER_ID, RUNE_INDEXER_CANISTER};
+use crate::config::read_config;
 
 #[derive(CandidType, Deserialize, Debug)]
 pub struct GetEtchingResult {
@@ -80,7 +80,7 @@ impl Service {
 }
 
 pub async fn get_etching(txid: String) -> Option<GetEtchingResult> {
-    let rune_indexer_principal = Principal::from_text(RUNE_INDEXER_CANISTER).ok()?;
+    let rune_indexer_principal = read_config(|c| c.rune_indexer_canister);
     let (result,): (Option<GetEtchingResult>,) =
         ic_cdk::api::call::call(rune_indexer_principal, "get_etching", (txid,))
             .await
@@ -1,7 +1,6 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
 
-use crate::exchange::exchange::CookiePools;
+use crate::exchange::exchange::{CookiePools, GamerGames, Gamers, __CustomStorageAccess};
 use crate::exchange::{CookiePoolState, UserAction};
 
 use crate::*;
@@ -14,7 +13,58 @@ use ree_exchange_sdk::prelude::PoolStorageAccess;
 use ree_exchange_sdk::types::{CoinId, InputCoin, OutputCoin};
 use serde::{Deserialize, Serialize};
 
-use super::gamer::Gamer;
+use super::gamer::{Gamer, TxProgress};
+use super::leaderboard;
+use super::referral;
+use crate::anti_bot;
+
+pub const ETCH_CHECK_BASE_BACKOFF: Seconds = 60;
+pub const ETCH_CHECK_MAX_BACKOFF: Seconds = 60 * 60;
+/// A game nobody registered in or claimed from for this long can be refunded by its gamers.
+pub const GAME_ABANDON_TIMEOUT: Seconds = 7 * 24 * 60 * 60;
+pub const MAX_GAME_NAME_LENGTH: usize = 64;
+/// The most records a single page of `list_games` or `list_gamers` returns.
+pub const MAX_PAGE_SIZE: u64 = 100;
+pub const BPS_DENOMINATOR: u16 = 10_000;
+/// Games created before the split was configurable gave 4/5 of the premine to the gamers.
+pub const DEFAULT_PLAYER_SHARE_BPS: u16 = 8_000;
+/// The most pools a game can spread its transactions over.
+pub const MAX_POOL_SHARDS: u8 = 8;
+/// The most gamers a single `batch_withdraw` pays out.
+pub const MAX_BATCH_WITHDRAW_SIZE: usize = 100;
+
+fn default_player_share_bps() -> u16 {
+    DEFAULT_PLAYER_SHARE_BPS
+}
+
+fn default_pool_shards() -> u8 {
+    1
+}
+
+/// `amount * bps / BPS_DENOMINATOR` rounded down, without overflowing for any `amount`
+/// as long as `bps <= BPS_DENOMINATOR`.
+pub fn share_of(amount: u128, bps: u16) -> u128 {
+    let bps = bps.min(BPS_DENOMINATOR) as u128;
+    let denominator = BPS_DENOMINATOR as u128;
+    amount / denominator * bps + amount % denominator * bps / denominator
+}
+
+/// The gamers need a share to play for and the pool needs some runes for the liquidity.
+pub fn validate_token_split(player_share_bps: u16, creator_share_bps: u16) -> Result<()> {
+    if player_share_bps == 0 {
+        return Err(ExchangeError::InvalidTokenSplit(
+            "player share must be greater than 0".to_string(),
+        ));
+    }
+    let total = player_share_bps as u32 + creator_share_bps as u32;
+    if total >= BPS_DENOMINATOR as u32 {
+        return Err(ExchangeError::InvalidTokenSplit(format!(
+            "player share {} and creator share {} leave no runes for the liquidity",
+            player_share_bps, creator_share_bps
+        )));
+    }
+    Ok(())
+}
 
 #[derive(CandidType, Deserialize, Serialize, Clone)]
 pub struct Game {
@@ -30,11 +80,150 @@ pub struct Game {
     pub rune_premine_amount: u128,
     pub rune_info: Option<RuneInfo>,
     pub claimed_cookies: u128,
-    pub gamers: HashMap<AddressStr, Gamer>,
+    pub gamer_count: u64,
     #[serde(default)]
     pub etch_rune_commit_tx: String,
+    #[serde(default)]
+    pub etch_check_count: u32,
+    #[serde(default)]
+    pub next_etch_check_time: SecondTimestamp,
+    #[serde(default)]
+    pub etch_error: Option<String>,
+    #[serde(default)]
+    pub add_liquidity_tx: Option<TxProgress>,
+    #[serde(default)]
+    pub last_active_time: SecondTimestamp,
+    #[serde(default)]
+    pub end_time: Option<SecondTimestamp>,
+    #[serde(default)]
+    pub unclaimed_cookies_policy: UnclaimedCookiesPolicy,
+    /// Cookies nobody claimed before the game ended, fixed when the game ends.
+    #[serde(default)]
+    pub unclaimed_cookies: u128,
+    /// The part of `unclaimed_cookies` that goes to the liquidity instead of the gamers.
+    #[serde(default)]
+    pub unclaimed_cookies_to_liquidity: u128,
+    /// The part of `unclaimed_cookies` left for the gamers who have not withdrawn yet.
+    #[serde(default)]
+    pub unclaimed_cookies_left: u128,
+    /// The cookies of the gamers who have not withdrawn yet, `unclaimed_cookies_left` is
+    /// split in proportion to them.
+    #[serde(default)]
+    pub cookies_left: u128,
+    /// The share of the premine the gamers can claim, in basis points.
+    #[serde(default = "default_player_share_bps")]
+    pub player_share_bps: u16,
+    /// The share of the premine the creator can withdraw once the game ended, in basis points.
+    #[serde(default)]
+    pub creator_share_bps: u16,
+    #[serde(default)]
+    pub is_creator_allocation_withdrawn: bool,
+    #[serde(default)]
+    pub creator_withdraw_tx: Option<TxProgress>,
+    #[serde(default)]
+    pub anti_bot: AntiBotSettings,
+    #[serde(default)]
+    pub reward_schedule: RewardSchedule,
+    /// Claims of all gamers together, drives `RewardSchedule::Halving`.
+    #[serde(default)]
+    pub claim_count: u64,
+    /// The cookies a gamer gets for each gamer registering with it as referrer.
+    #[serde(default)]
+    pub referral_bonus: u128,
+    /// The number of pools of the game, `pool_address` included.
+    #[serde(default = "default_pool_shards")]
+    pub pool_shards: u8,
+    /// The pools of shard 1 and up, which only collect register fees until the game ended.
+    #[serde(default)]
+    pub fee_pool_addresses: Vec<AddressStr>,
+    /// Fee pools whose register fees went to the liquidity already.
+    #[serde(default)]
+    pub fee_shards_with_liquidity: Vec<AddressStr>,
+    /// The gamers who paid their register fee to each fee pool, in the order of
+    /// `fee_pool_addresses`. Shard 0 has the others.
+    #[serde(default)]
+    pub fee_shard_gamer_counts: Vec<u64>,
+}
+
+/// What happens to the cookies nobody claimed when a game ends at its `end_time`.
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum UnclaimedCookiesPolicy {
+    #[default]
+    AddToLiquidity,
+    /// Split among the gamers in proportion to their cookies, the last gamer to withdraw
+    /// gets the rounding leftovers.
+    DistributeToGamers,
+}
+
+/// How many cookies a click is worth over the course of a game. Whatever the schedule,
+/// a claim never awards more than the cookies left, so games always end exactly.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RewardSchedule {
+    /// Every click is worth `claim_amount_per_click`.
+    #[default]
+    Constant,
+    /// Falls linearly from `claim_amount_per_click` with the claimed share of the
+    /// claimable cookies, down to `min_amount` when everything is claimed.
+    LinearDecay { min_amount: u128 },
+    /// Halves after every `interval` claims of all gamers together, never below 1.
+    Halving { interval: u64 },
+    /// Clicks within `window` seconds after the cooldown ended extend a streak. Each
+    /// click of the streak adds `bonus_bps_per_click` to the reward, up to `max_bonus_bps`.
+    Streak {
+        window: Seconds,
+        bonus_bps_per_click: u16,
+        max_bonus_bps: u16,
+    },
+}
+
+impl RewardSchedule {
+    pub fn validate(&self, claim_amount_per_click: u128, claimable_amount: u128) -> Result<()> {
+        let invalid = |reason: &str| Err(ExchangeError::InvalidGameArgs(reason.to_string()));
+        match self {
+            RewardSchedule::Constant => Ok(()),
+            RewardSchedule::LinearDecay { min_amount } => {
+                if *min_amount == 0 || *min_amount > claim_amount_per_click {
+                    return invalid("min amount must be between 1 and claim amount per click");
+                }
+                // `claim_amount` multiplies the decay range with the claimed cookies
+                (claim_amount_per_click - min_amount)
+                    .checked_mul(claimable_amount)
+                    .map(|_| ())
+                    .ok_or(ExchangeError::Overflow)
+            }
+            RewardSchedule::Halving { interval } if *interval == 0 => {
+                invalid("halving interval must be greater than 0")
+            }
+            RewardSchedule::Halving { .. } => Ok(()),
+            RewardSchedule::Streak {
+                window,
+                bonus_bps_per_click,
+                max_bonus_bps,
+            } => {
+                if *window == 0 || *bonus_bps_per_click == 0 {
+                    return invalid("streak window and bonus must be greater than 0");
+                }
+                if *max_bonus_bps > BPS_DENOMINATOR {
+                    return invalid("streak bonus must not exceed 10000 bps");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Optional measures against scripted claims, all off by default.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AntiBotSettings {
+    /// Each claim adds a secret extra cooldown of up to this many seconds to the next one.
+    pub cooldown_jitter: Seconds,
+    /// The most claims a principal may make in all games together per minute.
+    pub max_claims_per_minute: Option<u32>,
+    /// Claims have to echo the nonce returned by `get_claim_challenge`.
+    pub require_challenge: bool,
 }
 
+/// The gamers of a game are paged by `list_gamers`.
 #[derive(CandidType, Deserialize, Serialize, Clone)]
 pub struct GameAndPool {
     pub game: Game,
@@ -42,6 +231,76 @@ pub struct GameAndPool {
     pub pool_state: Option<CookiePoolState>,
 }
 
+/// The part of a game a game list needs, without the gamers and the pool.
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+pub struct GameSummary {
+    pub game_id: GameId,
+    pub game_name: String,
+    pub game_status: GameStatus,
+    pub creator: Principal,
+    pub creator_address: AddressStr,
+    pub pool_address: Option<AddressStr>,
+    pub rune_info: Option<RuneInfo>,
+    pub gamer_register_fee: Satoshi,
+    pub claim_cooling_down: Seconds,
+    pub claim_amount_per_click: u128,
+    pub claimable_amount: u128,
+    pub claimed_cookies: u128,
+    pub gamer_count: u64,
+    pub end_time: Option<SecondTimestamp>,
+}
+
+impl From<&Game> for GameSummary {
+    fn from(game: &Game) -> Self {
+        Self {
+            game_id: game.game_id.clone(),
+            game_name: game.game_name.clone(),
+            game_status: game.game_status.clone(),
+            creator: game.creator,
+            creator_address: game.creator_address.clone(),
+            pool_address: game.pool_address.clone(),
+            rune_info: game.rune_info.clone(),
+            gamer_register_fee: game.gamer_register_fee,
+            claim_cooling_down: game.claim_cooling_down,
+            claim_amount_per_click: game.claim_amount_per_click,
+            claimable_amount: game.claimable_amount(),
+            claimed_cookies: game.claimed_cookies,
+            gamer_count: game.gamer_count,
+            end_time: game.end_time,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GameSort {
+    #[default]
+    Newest,
+    Oldest,
+    MostGamers,
+    MostClaimed,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ListGamesArgs {
+    pub offset: u64,
+    /// Capped at `MAX_PAGE_SIZE`.
+    pub limit: u64,
+    /// Matches the status regardless of the error text of `EtchingFailed`.
+    pub status_filter: Option<GameStatus>,
+    pub creator_filter: Option<Principal>,
+    pub sort: Option<GameSort>,
+}
+
+impl ListGamesArgs {
+    pub fn matches(&self, game: &Game) -> bool {
+        self.status_filter.as_ref().map_or(true, |status| {
+            std::mem::discriminant(status) == std::mem::discriminant(&game.game_status)
+        }) && self
+            .creator_filter
+            .map_or(true, |creator| creator == game.creator)
+    }
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone)]
 pub struct RuneInfo {
     pub rune_id: CoinId,
@@ -81,104 +340,583 @@ impl Game {
             rune_info: None,
             rune_premine_amount: args.rune_premine_amount,
             claimed_cookies: 0,
-            gamers: HashMap::new(),
+            gamer_count: 0,
             etch_rune_commit_tx: "".to_string(),
+            etch_check_count: 0,
+            next_etch_check_time: 0,
+            etch_error: None,
+            add_liquidity_tx: None,
+            last_active_time: 0,
+            end_time: args.end_time,
+            unclaimed_cookies_policy: args.unclaimed_cookies_policy.unwrap_or_default(),
+            unclaimed_cookies: 0,
+            unclaimed_cookies_to_liquidity: 0,
+            unclaimed_cookies_left: 0,
+            cookies_left: 0,
+            player_share_bps: args.player_share_bps.unwrap_or(DEFAULT_PLAYER_SHARE_BPS),
+            creator_share_bps: args.creator_share_bps.unwrap_or_default(),
+            is_creator_allocation_withdrawn: false,
+            creator_withdraw_tx: None,
+            anti_bot: args.anti_bot.unwrap_or_default(),
+            reward_schedule: args.reward_schedule.unwrap_or_default(),
+            claim_count: 0,
+            referral_bonus: args.referral_bonus.unwrap_or_default(),
+            pool_shards: args.pool_shards.unwrap_or(1),
+            fee_pool_addresses: vec![],
+            fee_shards_with_liquidity: vec![],
+            fee_shard_gamer_counts: vec![],
         }
     }
 
+    /// Game ids are decimal counters, their string order is not the creation order.
+    pub fn creation_order(&self) -> u64 {
+        self.game_id.parse::<u64>().unwrap_or(u64::MAX)
+    }
+
+    pub fn is_etch_check_due(&self, now: SecondTimestamp) -> bool {
+        matches!(self.game_status, GameStatus::Etching)
+            && !self.etch_rune_commit_tx.is_empty()
+            && now >= self.next_etch_check_time
+    }
+
+    pub fn record_etch_check_failure(&mut self, error: String, now: SecondTimestamp) {
+        self.etch_check_count = self.etch_check_count.saturating_add(1);
+        let backoff = ETCH_CHECK_BASE_BACKOFF
+            .saturating_mul(1 << self.etch_check_count.min(6))
+            .min(ETCH_CHECK_MAX_BACKOFF);
+        self.next_etch_check_time = now.saturating_add(backoff);
+        self.etch_error = Some(error);
+    }
+
+    pub fn fail_etching(&mut self, err_info: String) -> Result<()> {
+        self.game_status = self.game_status.fail_etching(err_info.clone())?;
+        self.etch_error = Some(err_info);
+        Ok(())
+    }
+
+    pub fn clear_etch_checks(&mut self) {
+        self.etch_check_count = 0;
+        self.next_etch_check_time = 0;
+        self.etch_error = None;
+    }
+
     pub fn key_path(&self) -> String {
         format!("game_pool_{}", self.game_id.to_string())
     }
 
+    /// Shard 0 keeps the key path of the single pool games had before, since the premine
+    /// of a game is etched to it.
+    pub fn shard_key_path(&self, shard: u8) -> String {
+        if shard == 0 {
+            self.key_path()
+        } else {
+            format!("game_pool_{}_{}", self.game_id, shard)
+        }
+    }
+
+    /// The addresses of all pools of the game, indexed by shard.
+    pub fn pool_addresses(&self) -> Vec<AddressStr> {
+        self.pool_address
+            .iter()
+            .chain(self.fee_pool_addresses.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn pool_shard(&self, pool_address: &AddressStr) -> Result<u8> {
+        self.pool_addresses()
+            .iter()
+            .position(|address| address == pool_address)
+            .map(|shard| shard as u8)
+            .ok_or(ExchangeError::PoolAddressMismatch {
+                expected: self.pool_address.clone().unwrap_or_default(),
+                actual: pool_address.clone(),
+            })
+    }
+
+    /// The number of gamers who paid their register fee into a shard.
+    pub fn shard_gamer_count(&self, shard: u8) -> u64 {
+        match shard {
+            0 => self
+                .gamer_count
+                .saturating_sub(self.fee_shard_gamer_counts.iter().sum()),
+            _ => self
+                .fee_shard_gamer_counts
+                .get(shard as usize - 1)
+                .copied()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The register fees paid into a shard, which it adds to the liquidity.
+    pub fn shard_register_fees(&self, shard: u8) -> Result<u128> {
+        (self.shard_gamer_count(shard) as u128)
+            .checked_mul(self.gamer_register_fee as u128)
+            .ok_or(ExchangeError::Overflow)
+    }
+
+    /// Records the pool a registered gamer paid the register fee into.
+    pub fn assign_pool_shard(&mut self, gamer_id: &AddressStr, shard: u8) -> Result<()> {
+        let previous = self.update_gamer(gamer_id, |gamer| {
+            std::mem::replace(&mut gamer.pool_shard, shard)
+        })?;
+        self.uncount_shard_gamer(previous);
+        if shard > 0 {
+            let index = shard as usize - 1;
+            if self.fee_shard_gamer_counts.len() <= index {
+                self.fee_shard_gamer_counts.resize(index + 1, 0);
+            }
+            self.fee_shard_gamer_counts[index] += 1;
+        }
+        Ok(())
+    }
+
+    fn uncount_shard_gamer(&mut self, shard: u8) {
+        if shard > 0 {
+            if let Some(count) = self.fee_shard_gamer_counts.get_mut(shard as usize - 1) {
+                *count = count.saturating_sub(1);
+            }
+        }
+    }
+
+    /// The pool a register should be sent to: the shard with the fewest unfinalized states.
+    /// It has the fewest transactions in flight, so a transaction built on its last state
+    /// is the least likely to hit `PoolStateExpired`.
+    pub fn route_register_pool(&self) -> Result<AddressStr> {
+        self.pool_addresses()
+            .into_iter()
+            .filter_map(|address| CookiePools::get(&address).map(|pool| (address, pool)))
+            .min_by_key(|(_, pool)| pool.states().len())
+            .map(|(address, _)| address)
+            .ok_or(ExchangeError::PoolAddressNotFound)
+    }
+
+    /// Only registrations are sharded, the runes stay in shard 0 where the premine was
+    /// etched to, so everything paying out runes has to go through it.
+    fn ensure_rune_pool(&self, pool_address: &AddressStr) -> Result<()> {
+        if self.pool_shard(pool_address)? != 0 {
+            return Err(ExchangeError::PoolAddressMismatch {
+                expected: self.pool_address.clone().unwrap_or_default(),
+                actual: pool_address.clone(),
+            });
+        }
+        Ok(())
+    }
+
     pub fn premine_rune_amount(&self) -> u128 {
         self.rune_premine_amount
     }
 
     pub fn claimable_amount(&self) -> u128 {
-        self.rune_premine_amount * 4 / 5
+        share_of(self.rune_premine_amount, self.player_share_bps)
+    }
+
+    pub fn creator_allocation(&self) -> u128 {
+        share_of(self.rune_premine_amount, self.creator_share_bps)
+    }
+
+    /// Everything the gamers and the creator don't get, so the rounding leftovers go to the pool.
+    pub fn calculate_add_liquidity_rune_amount(&self) -> Result<u128> {
+        self.rune_premine_amount
+            .checked_sub(self.claimable_amount())
+            .and_then(|v| v.checked_sub(self.creator_allocation()))
+            .and_then(|v| v.checked_add(self.unclaimed_cookies_to_liquidity))
+            .ok_or(ExchangeError::Overflow)
+    }
+
+    /// The share of `unclaimed_cookies` a gamer gets on top of the claimed cookies. Every
+    /// withdraw takes its share of what is left, so the shares always add up exactly.
+    pub fn unclaimed_cookies_bonus(&self, gamer: &Gamer) -> Result<u128> {
+        if gamer.is_withdrawn {
+            return Ok(gamer.unclaimed_cookies_bonus);
+        }
+        if !self.distributes_unclaimed_cookies() || self.cookies_left == 0 {
+            return Ok(0);
+        }
+        self.unclaimed_cookies_left
+            .checked_mul(gamer.cookies.min(self.cookies_left))
+            .map(|v| v / self.cookies_left)
+            .ok_or(ExchangeError::Overflow)
+    }
+
+    /// Whether the gamers of an ended game split its unclaimed cookies.
+    fn distributes_unclaimed_cookies(&self) -> bool {
+        self.unclaimed_cookies_policy == UnclaimedCookiesPolicy::DistributeToGamers
+            && self.unclaimed_cookies > 0
+            && self.claimed_cookies > 0
+    }
+
+    pub fn withdrawable_cookies(&self, gamer: &Gamer) -> Result<u128> {
+        gamer
+            .cookies
+            .checked_add(self.unclaimed_cookies_bonus(gamer)?)
+            .ok_or(ExchangeError::Overflow)
+    }
+
+    fn gamer_key(&self, gamer_id: &AddressStr) -> (GameId, AddressStr) {
+        (self.game_id.clone(), gamer_id.clone())
+    }
+
+    pub fn get_gamer(&self, gamer_id: &AddressStr) -> Option<Gamer> {
+        Gamers::with(|m| m.get(&self.gamer_key(gamer_id)))
     }
 
-    pub fn calculate_add_liquidity_rune_amount(&self) -> u128 {
-        self.premine_rune_amount() / 5
+    pub fn contains_gamer(&self, gamer_id: &AddressStr) -> bool {
+        Gamers::with(|m| m.contains_key(&self.gamer_key(gamer_id)))
+    }
+
+    pub fn save_gamer(&self, gamer: Gamer) {
+        Gamers::with_mut(|m| {
+            m.insert(self.gamer_key(&gamer.address), gamer);
+        });
+    }
+
+    pub fn update_gamer<F, R>(&self, gamer_id: &AddressStr, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Gamer) -> R,
+    {
+        let mut gamer = self
+            .get_gamer(gamer_id)
+            .ok_or(ExchangeError::GamerNotFound(gamer_id.clone()))?;
+        let r = f(&mut gamer);
+        self.save_gamer(gamer);
+        Ok(r)
+    }
+
+    pub fn gamers(&self) -> Vec<Gamer> {
+        Gamers::with(|m| {
+            m.range((self.game_id.clone(), AddressStr::new())..)
+                .take_while(|e| e.key().0 == self.game_id)
+                .map(|e| e.value())
+                .collect()
+        })
+    }
+
+    pub fn gamers_page(&self, offset: u64, limit: u64) -> Vec<Gamer> {
+        Gamers::with(|m| {
+            m.range((self.game_id.clone(), AddressStr::new())..)
+                .take_while(|e| e.key().0 == self.game_id)
+                .skip(offset as usize)
+                .take(limit.min(MAX_PAGE_SIZE) as usize)
+                .map(|e| e.value())
+                .collect()
+        })
     }
 
     pub fn register_new_gamer(&mut self, gamer_id: AddressStr) -> Result<()> {
-        if self.gamers.contains_key(&gamer_id) {
+        if self.contains_gamer(&gamer_id) {
             return Err(ExchangeError::GamerAlreadyExist(gamer_id));
         }
 
-        self.gamers
-            .insert(gamer_id.clone(), Gamer::new(gamer_id.clone()));
+        self.gamer_count = self.gamer_count.checked_add(1).ok_or(ExchangeError::Overflow)?;
+        GamerGames::with_mut(|m| {
+            m.insert((gamer_id.clone(), self.game_id.clone()), ());
+        });
+        self.save_gamer(Gamer::new(gamer_id));
 
         Ok(())
     }
 
+    pub fn remove_gamer(&mut self, gamer_id: &AddressStr) -> Option<Gamer> {
+        let removed = Gamers::with_mut(|m| m.remove(&self.gamer_key(gamer_id)));
+        if let Some(gamer) = &removed {
+            self.gamer_count = self.gamer_count.saturating_sub(1);
+            self.uncount_shard_gamer(gamer.pool_shard);
+            GamerGames::with_mut(|m| m.remove(&(gamer_id.clone(), self.game_id.clone())));
+            leaderboard::record_gamer_removed(&self.game_id, gamer);
+            referral::remove_referral(&self.game_id, gamer_id);
+        }
+        removed
+    }
+
     pub fn is_end(&self) -> bool {
         self.claimable_amount() == self.claimed_cookies
+            || matches!(
+                self.game_status,
+                GameStatus::WaitAddedLiquidity | GameStatus::Withdrawing
+            )
     }
 
-    pub fn able_claim(&self, gamer_id: AddressStr) -> Result<()> {
-        if self.is_end() {
+    pub fn is_expired(&self, now: SecondTimestamp) -> bool {
+        self.end_time.map_or(false, |end_time| now >= end_time)
+    }
+
+    /// Ends a running game whose `end_time` has passed, returns whether it was ended.
+    pub fn end_if_expired(&mut self, now: SecondTimestamp) -> Result<bool> {
+        if matches!(self.game_status, GameStatus::Playing | GameStatus::Paused)
+            && self.is_expired(now)
+        {
+            self.end()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// The end of the base cooldown. The jitter on top of it is never reported, so clients
+    /// can't time their claims to the second.
+    pub fn next_claim_time(&self, gamer: &Gamer) -> SecondTimestamp {
+        gamer
+            .last_click_time
+            .saturating_add(self.claim_cooling_down)
+    }
+
+    fn claim_deadline(&self, gamer: &Gamer) -> SecondTimestamp {
+        let jitter = anti_bot::cooldown_jitter(self.anti_bot.cooldown_jitter, self, gamer);
+        self.next_claim_time(gamer).saturating_add(jitter)
+    }
+
+    pub fn game_and_gamer(
+        &self,
+        gamer: Option<Gamer>,
+        now: SecondTimestamp,
+    ) -> Result<GameAndGamer> {
+        let withdrawable_cookies = match &gamer {
+            Some(gamer) => Some(self.withdrawable_cookies(gamer)?),
+            None => None,
+        };
+        Ok(GameAndGamer {
+            is_end: self.is_end() || self.is_expired(now),
+            gamer_register_fee: self.gamer_register_fee,
+            claim_cooling_down: self.claim_cooling_down,
+            cookie_amount_per_claim: self.claim_amount_per_click,
+            claimed_cookies: self.claimed_cookies,
+            next_claim_time: gamer.as_ref().map(|g| self.next_claim_time(g)),
+            gamer,
+            game_id: self.game_id.clone(),
+            game_name: self.game_name.clone(),
+            game_status: self.game_status.clone(),
+            withdrawable_cookies,
+        })
+    }
+
+    pub fn able_claim(&self, gamer: &Gamer, now: SecondTimestamp) -> Result<()> {
+        if matches!(self.game_status, GameStatus::Paused) {
+            return Err(ExchangeError::GamePaused(self.game_id.clone()));
+        }
+
+        if self.is_end() || self.is_expired(now) {
             return Err(ExchangeError::GameEnd);
         }
 
-        self.gamers
-            .get(&gamer_id)
-            .ok_or(ExchangeError::GamerNotFound(gamer_id.clone()))
-            .and_then(|gamer| {
-                if get_chain_second_timestamp() > gamer.last_click_time + self.claim_cooling_down {
-                    Ok(())
-                } else {
-                    Err(ExchangeError::GamerCoolingDown(
-                        gamer_id,
-                        gamer.last_click_time + self.claim_cooling_down,
-                    ))
-                }
-            })
+        if !matches!(self.game_status, GameStatus::Playing) {
+            return Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Playing,
+                self.game_status.clone(),
+            ));
+        }
+
+        if now > self.claim_deadline(gamer) {
+            Ok(())
+        } else {
+            Err(ExchangeError::GamerCoolingDown(
+                gamer.address.clone(),
+                self.next_claim_time(gamer),
+            ))
+        }
     }
 
-    pub fn end(&mut self) {
-        self.game_status = self.game_status.game_end();
+    pub fn is_abandoned(&self, now: SecondTimestamp) -> bool {
+        matches!(self.game_status, GameStatus::Playing | GameStatus::Paused)
+            && now >= self.last_active_time.saturating_add(GAME_ABANDON_TIMEOUT)
     }
 
-    pub fn claim(&mut self, gamer_id: AddressStr) -> Result<u128> {
-        self.able_claim(gamer_id.clone())?;
+    pub fn is_refundable(&self, now: SecondTimestamp) -> bool {
+        matches!(self.game_status, GameStatus::Cancelled) || self.is_abandoned(now)
+    }
 
-        let gamer = self
-            .gamers
-            .get_mut(&gamer_id)
-            .ok_or(ExchangeError::GamerNotFound(gamer_id.clone()))?;
+    pub fn end(&mut self) -> Result<()> {
+        self.game_status = self.game_status.game_end()?;
+        self.unclaimed_cookies = self
+            .claimable_amount()
+            .checked_sub(self.claimed_cookies)
+            .ok_or(ExchangeError::Overflow)?;
+        if !self.distributes_unclaimed_cookies() {
+            self.unclaimed_cookies_to_liquidity = self.unclaimed_cookies;
+            return Ok(());
+        }
+        // the gamers take their shares as they withdraw, see `unclaimed_cookies_bonus`
+        self.unclaimed_cookies_left = self.unclaimed_cookies;
+        self.cookies_left = self.claimed_cookies;
+        self.unclaimed_cookies_to_liquidity = 0;
+        Ok(())
+    }
 
-        self.claimed_cookies = self
-            .claimed_cookies
-            .checked_add(self.claim_amount_per_click)
+    /// The streak `gamer` has after a click at `now`, 0 when the click breaks the streak.
+    pub fn streak_at(&self, gamer: &Gamer, now: SecondTimestamp) -> u32 {
+        let RewardSchedule::Streak { window, .. } = self.reward_schedule else {
+            return 0;
+        };
+        let on_time =
+            gamer.last_click_time != 0 && now <= self.claim_deadline(gamer).saturating_add(window);
+        if on_time {
+            gamer.streak.saturating_add(1)
+        } else {
+            0
+        }
+    }
+
+    /// The cookies a click of `gamer` at `now` is worth, clamped to the cookies left.
+    pub fn claim_amount(&self, gamer: &Gamer, now: SecondTimestamp) -> Result<u128> {
+        let per_click = self.claim_amount_per_click;
+        let amount = match &self.reward_schedule {
+            RewardSchedule::Constant => per_click,
+            RewardSchedule::LinearDecay { min_amount } => {
+                let decay = per_click
+                    .saturating_sub(*min_amount)
+                    .checked_mul(self.claimed_cookies)
+                    .ok_or(ExchangeError::Overflow)?
+                    / self.claimable_amount().max(1);
+                per_click.saturating_sub(decay)
+            }
+            RewardSchedule::Halving { interval } => {
+                let halvings = self.claim_count / (*interval).max(1);
+                (per_click >> halvings.min(127)).max(1)
+            }
+            RewardSchedule::Streak {
+                bonus_bps_per_click,
+                max_bonus_bps,
+                ..
+            } => {
+                let bonus_bps = (self.streak_at(gamer, now) as u64)
+                    .saturating_mul(*bonus_bps_per_click as u64)
+                    .min(*max_bonus_bps as u64) as u16;
+                per_click
+                    .checked_add(share_of(per_click, bonus_bps))
+                    .ok_or(ExchangeError::Overflow)?
+            }
+        };
+        let remaining = self
+            .claimable_amount()
+            .checked_sub(self.claimed_cookies)
             .ok_or(ExchangeError::Overflow)?;
-        gamer.claim(self.claim_amount_per_click)?;
+        Ok(amount.min(remaining))
+    }
 
+    pub fn claim(&mut self, gamer_id: AddressStr, now: SecondTimestamp) -> Result<u128> {
+        let mut gamer = self
+            .get_gamer(&gamer_id)
+            .ok_or(ExchangeError::GamerNotFound(gamer_id.clone()))?;
+        self.able_claim(&gamer, now)?;
+
+        let old_cookies_balance = gamer.cookies;
+        let amount = self.apply_claim(&mut gamer, now)?;
         let new_cookies_balance = gamer.cookies;
 
         if self.is_end() {
-            self.end();
+            self.end()?;
         }
+        self.last_active_time = now;
+        self.save_gamer(gamer);
+        leaderboard::record_claim(&self.game_id, &gamer_id, old_cookies_balance, amount);
 
         Ok(new_cookies_balance)
     }
 
+    /// Books one click of `gamer` at `now` on the game and the gamer, without checking
+    /// whether the gamer may claim and without storing anything. Returns the cookies claimed.
+    pub fn apply_claim(&mut self, gamer: &mut Gamer, now: SecondTimestamp) -> Result<u128> {
+        let amount = self.claim_amount(gamer, now)?;
+        self.claimed_cookies = self
+            .claimed_cookies
+            .checked_add(amount)
+            .ok_or(ExchangeError::Overflow)?;
+        self.claim_count = self.claim_count.saturating_add(1);
+        gamer.streak = self.streak_at(gamer, now);
+        gamer.claim(amount, now)?;
+        Ok(amount)
+    }
+
     pub fn withdraw(&mut self, gamer_id: AddressStr) -> Result<u128> {
         if !self.is_end() {
             return Err(ExchangeError::GameNotEnd);
         }
-        let gamer = self
-            .gamers
-            .get_mut(&gamer_id)
+        let mut gamer = self
+            .get_gamer(&gamer_id)
             .ok_or(ExchangeError::GamerNotFound(gamer_id.clone()))?;
 
+        let cookies = self.settle_withdraw(&mut gamer)?;
+        self.save_gamer(gamer);
+        leaderboard::record_withdraw(&gamer_id, cookies);
+        Ok(cookies)
+    }
+
+    /// Marks a gamer as withdrawn and takes the gamer's share out of the unclaimed cookies
+    /// left, returns the cookies paid out. The gamer is not saved.
+    pub fn settle_withdraw(&mut self, gamer: &mut Gamer) -> Result<u128> {
+        if gamer.is_withdrawn {
+            return Err(ExchangeError::GamerWithdrawRepeatedly(
+                gamer.address.clone(),
+            ));
+        }
+        let bonus = self.unclaimed_cookies_bonus(gamer)?;
+        let cookies = gamer
+            .cookies
+            .checked_add(bonus)
+            .ok_or(ExchangeError::Overflow)?;
+        if self.distributes_unclaimed_cookies() {
+            self.unclaimed_cookies_left = self.unclaimed_cookies_left.saturating_sub(bonus);
+            self.cookies_left = self.cookies_left.saturating_sub(gamer.cookies);
+        }
+        gamer.is_withdrawn = true;
+        gamer.unclaimed_cookies_bonus = bonus;
+        Ok(cookies)
+    }
+
+    /// Undoes `settle_withdraw` after its tx was rolled back, returns the cookies it paid out.
+    /// The gamer is not saved.
+    pub fn unsettle_withdraw(&mut self, gamer: &mut Gamer) -> u128 {
         if !gamer.is_withdrawn {
-            gamer.is_withdrawn = true;
-            Ok(gamer.cookies)
-        } else {
-            Err(ExchangeError::GamerWithdrawRepeatedly(gamer_id))
+            return 0;
+        }
+        let bonus = std::mem::take(&mut gamer.unclaimed_cookies_bonus);
+        if self.distributes_unclaimed_cookies() {
+            self.unclaimed_cookies_left = self.unclaimed_cookies_left.saturating_add(bonus);
+            self.cookies_left = self.cookies_left.saturating_add(gamer.cookies);
+        }
+        gamer.is_withdrawn = false;
+        gamer.cookies.saturating_add(bonus)
+    }
+
+    pub fn withdraw_creator_allocation(&mut self, address: &AddressStr) -> Result<u128> {
+        if !self.is_end() {
+            return Err(ExchangeError::GameNotEnd);
+        }
+        if address != &self.creator_address {
+            return Err(ExchangeError::NotGameCreator(address.clone()));
         }
+        let allocation = self.creator_allocation();
+        if allocation == 0 {
+            return Err(ExchangeError::NoCreatorAllocation(self.game_id.clone()));
+        }
+        if self.is_creator_allocation_withdrawn {
+            return Err(ExchangeError::CreatorWithdrawRepeatedly(
+                self.game_id.clone(),
+            ));
+        }
+        self.is_creator_allocation_withdrawn = true;
+        Ok(allocation)
+    }
+
+    /// Marks the register fee of a gamer as paid back. The first refund of an
+    /// abandoned game cancels it, so that nobody can claim any more.
+    pub fn refund(&mut self, gamer_id: AddressStr, now: SecondTimestamp) -> Result<u64> {
+        if !self.is_refundable(now) {
+            return Err(ExchangeError::GameNotRefundable(self.game_id.clone()));
+        }
+        let mut gamer = self
+            .get_gamer(&gamer_id)
+            .ok_or(ExchangeError::GamerNotFound(gamer_id.clone()))?;
+        if gamer.is_refunded {
+            return Err(ExchangeError::GamerRefundRepeatedly(gamer_id));
+        }
+
+        if self.is_abandoned(now) {
+            self.game_status = self.game_status.cancel()?;
+        }
+        gamer.is_refunded = true;
+        self.save_gamer(gamer);
+        Ok(self.gamer_register_fee)
     }
 }
 
@@ -192,85 +930,78 @@ impl Game {
         pool_utxo_received: Vec<Utxo>,
         input_coins: Vec<InputCoin>,
         output_coins: Vec<OutputCoin>,
-    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Utxo))> {
+    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Option<Utxo>))> {
         let rune_info = self
             .rune_info
             .as_ref()
             .ok_or(ExchangeError::RuneNotFound(self.game_name.clone()))?;
 
-        let btc_pool = CookiePools::get(&pool_address)
-            .ok_or(ExchangeError::PoolNotFound(pool_address.clone()))?;
-
-        let last_state = btc_pool.states().last().ok_or(ExchangeError::InvalidState(
-            "Pool has no states".to_string(),
-        ))?;
+        // shard 0 adds the liquidity runes, every shard adds the register fees paid into it
+        let shard = self.pool_shard(&pool_address)?;
+        let pool_expected_spend_btc = self.shard_register_fees(shard)?;
+        if shard > 0 {
+            if !matches!(
+                self.game_status,
+                GameStatus::WaitAddedLiquidity | GameStatus::Withdrawing
+            ) {
+                return Err(ExchangeError::GameNotEnd);
+            }
+            if self.fee_shards_with_liquidity.contains(&pool_address) {
+                return Err(ExchangeError::InvalidState(format!(
+                    "Liquidity of pool {} already added",
+                    pool_address
+                )));
+            }
+            (input_coins.is_empty()
+                && output_coins.len() == 1
+                && output_coins[0].coin.id.eq(&CoinId::btc())
+                && output_coins[0].coin.value == pool_expected_spend_btc)
+                .then(|| ())
+                .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
+                    "input_coins: {:?}, output_coins: {:?}",
+                    input_coins, output_coins
+                )))?;
+        } else {
+            let pool_expected_spend_rune = self.calculate_add_liquidity_rune_amount()?;
 
-        let pool_expected_spend_rune = self.calculate_add_liquidity_rune_amount();
-        let pool_expected_spend_btc = self.gamers.len() as u128 * self.gamer_register_fee as u128;
+            // the input coins should be only one and the value should be equal to the register fee
+            let output_btc = output_coins
+                .iter()
+                .find(|c| c.coin.id.eq(&CoinId::btc()))
+                .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                    "output_coins should contain btc coin".to_string(),
+                ))?;
+            let output_rune = output_coins
+                .iter()
+                .find(|c| c.coin.id.eq(&rune_info.rune_id))
+                .ok_or(ExchangeError::InvalidSignPsbtArgs(
+                    "output_coins should contain rune coin".to_string(),
+                ))?;
+            (
+                input_coins.len() == 0
+                && output_coins.len() == 2
+                && output_btc.coin.id.eq(&CoinId::btc())
+                && output_btc.coin.value == pool_expected_spend_btc
+                // && output_btc.to.eq(&richswap_pool_address)
+                && output_rune.coin.id.eq(&rune_info.rune_id)
+                && output_rune.coin.value == pool_expected_spend_rune
+                // && output_rune.to.eq(&richswap_pool_address)
+            )
+                .then(|| ())
+                .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
+                    "input_coins: {:?}, output_coins: {:?}",
+                    input_coins, output_coins
+                )))?;
+        }
 
-        // the input coins should be only one and the value should be equal to the register fee
-        let output_btc = output_coins
-            .iter()
-            .find(|c| c.coin.id.eq(&CoinId::btc()))
-            .ok_or(ExchangeError::InvalidSignPsbtArgs(
-                "output_coins should contain btc coin".to_string(),
-            ))?;
-        let output_rune = output_coins
-            .iter()
-            .find(|c| c.coin.id.eq(&rune_info.rune_id))
-            .ok_or(ExchangeError::InvalidSignPsbtArgs(
-                "output_coins should contain rune coin".to_string(),
-            ))?;
-        (
-            input_coins.len() == 0
-            && output_coins.len() == 2
-            && output_btc.coin.id.eq(&CoinId::btc())
-            && output_btc.coin.value == pool_expected_spend_btc
-            // && output_btc.to.eq(&richswap_pool_address)
-            && output_rune.coin.id.eq(&rune_info.rune_id)
-            && output_rune.coin.value == pool_expected_spend_rune
-            // && output_rune.to.eq(&richswap_pool_address)
+        self.spend_last_state(
+            pool_address,
+            txid,
+            nonce,
+            pool_utxo_spent,
+            pool_utxo_received,
+            UserAction::AddLiquidity,
         )
-            .then(|| ())
-            .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
-                "input_coins: {:?}, output_coins: {:?}",
-                input_coins, output_coins
-            )))?;
-
-        // check nonce matches
-        (last_state.nonce == nonce)
-            .then(|| ())
-            .ok_or(ExchangeError::PoolStateExpired(last_state.nonce))?;
-
-        (pool_utxo_spent.len() == 1 && pool_utxo_spent.contains(&last_state.utxo.outpoint()))
-            .then(|| ())
-            .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
-            "Pool Utxo Spend not eq last pool state utxos, pool_utxo_spent: {:?}, last_state: {:?}",
-            pool_utxo_spent, last_state
-        )))?;
-
-        // the pool_utxo_receive should exist
-        let new_utxo = pool_utxo_received.last().map(|s| s.clone()).ok_or(
-            ExchangeError::InvalidSignPsbtArgs("pool_utxo_receive not found".to_string()),
-        )?;
-
-        let new_state = CookiePoolState {
-            txid: txid,
-            nonce: last_state
-                .nonce
-                .checked_add(1)
-                .ok_or(ExchangeError::Overflow)?,
-            utxo: new_utxo,
-            user_action: UserAction::AddLiquidity,
-        };
-
-        return Ok((
-            new_state,
-            (
-                btc_pool.metadata().key_derivation_path.clone(),
-                last_state.utxo.clone(),
-            ),
-        ));
     }
 
     pub fn validate_register(
@@ -283,18 +1014,27 @@ impl Game {
         input_coins: Vec<InputCoin>,
         output_coins: Vec<OutputCoin>,
         address: AddressStr,
-    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Utxo))> {
-        if !matches!(self.game_status, GameStatus::Playing) {
+        referrer: Option<&AddressStr>,
+    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Option<Utxo>))> {
+        if matches!(self.game_status, GameStatus::Paused) {
+            return Err(ExchangeError::GamePaused(self.game_id.clone()));
+        }
+
+        if !matches!(self.game_status, GameStatus::Playing) {
             return Err(ExchangeError::GameStatusNotMatch(
-                self.game_status.clone(),
                 GameStatus::Playing,
+                self.game_status.clone(),
             ));
         }
 
-        if self.gamers.contains_key(&address) {
+        if self.contains_gamer(&address) {
             return Err(ExchangeError::GamerAlreadyExist(address.clone()));
         }
 
+        if let Some(referrer) = referrer {
+            referral::validate_referral(self, &address, referrer)?;
+        }
+
         // the input coins should be only one and the value should be equal to the register fee
         (input_coins.len() == 1
             && output_coins.is_empty()
@@ -306,63 +1046,116 @@ impl Game {
                 input_coins, output_coins
             )))?;
 
+        self.spend_last_state(
+            pool_address,
+            txid,
+            nonce,
+            pool_utxo_spent,
+            pool_utxo_received,
+            UserAction::Register(self.game_id.clone(), address),
+        )
+    }
+
+    /// Spends the last state of the pool at `pool_address` in a single UTXO, checking the
+    /// nonce of the intention, and builds the state `action` leaves the pool in.
+    /// Returns the UTXO spent, which only a register into an empty fee shard has none of.
+    fn spend_last_state(
+        &self,
+        pool_address: AddressStr,
+        txid: Txid,
+        nonce: u64,
+        pool_utxo_spend: Vec<String>,
+        pool_utxo_received: Vec<Utxo>,
+        action: UserAction,
+    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Option<Utxo>))> {
+        let shard = self.pool_shard(&pool_address)?;
         let btc_pool = CookiePools::get(&pool_address)
             .ok_or(ExchangeError::PoolNotFound(pool_address.clone()))?;
 
-        let last_state = btc_pool.states().last().ok_or(ExchangeError::InvalidState(
-            "Pool has no states".to_string(),
-        ))?;
+        // a fee shard has no state until its first register pays into it
+        let last_state = btc_pool.states().last();
+        let last_nonce = match last_state {
+            Some(last_state) => last_state.nonce,
+            None if shard > 0 && matches!(action, UserAction::Register(..)) => 0,
+            None => {
+                return Err(ExchangeError::InvalidState(
+                    "Pool has no states".to_string(),
+                ))
+            }
+        };
 
         // check nonce matches
-        (last_state.nonce == nonce)
-            .then(|| ())
-            .ok_or(ExchangeError::PoolStateExpired(last_state.nonce))?;
-
-        (pool_utxo_spent.len() == 1 && pool_utxo_spent.contains(&last_state.utxo.outpoint()))
-            .then(|| ())
-            .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
-            "Pool Utxo Spend not eq last pool state utxos, pool_utxo_spent: {:?}, last_state: {:?}",
-            pool_utxo_spent, last_state
-        )))?;
-
-        last_state
-            .utxo
-            .outpoint()
-            .eq(pool_utxo_spent
-                .last()
-                .ok_or(ExchangeError::InvalidSignPsbtArgs(
-                    "pool_utxo_spend is empty".to_string(),
-                ))?)
+        (last_nonce == nonce)
             .then(|| ())
-            .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
-                "pool_utxo_spend: {:?}, last_state_utxo: {:?}",
-                pool_utxo_spent, last_state.utxo
-            )))?;
+            .ok_or(ExchangeError::PoolStateExpired(last_nonce))?;
+
+        match last_state {
+            Some(last_state) => {
+                (pool_utxo_spend.len() == 1
+                    && pool_utxo_spend.contains(&last_state.utxo.outpoint()))
+                .then(|| ())
+                .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
+                    "Pool Utxo Spend not eq last pool state utxos, pool_utxo_spend: {:?}, last_state: {:?}",
+                    pool_utxo_spend, last_state
+                )))?;
+            }
+            None => {
+                pool_utxo_spend.is_empty().then(|| ()).ok_or(
+                    ExchangeError::InvalidSignPsbtArgs(format!(
+                        "Pool has no utxo to spend, pool_utxo_spend: {:?}",
+                        pool_utxo_spend
+                    )),
+                )?;
+            }
+        }
 
         // the pool_utxo_receive should exist
-        let new_utxo = pool_utxo_received.last().map(|s| s.clone()).ok_or(
+        let new_utxo = pool_utxo_received.first().map(|s| s.clone()).ok_or(
             ExchangeError::InvalidSignPsbtArgs("pool_utxo_receive not found".to_string()),
         )?;
 
         let new_state = CookiePoolState {
             txid: txid,
-            nonce: last_state
-                .nonce
-                .checked_add(1)
-                .ok_or(ExchangeError::Overflow)?,
+            nonce: last_nonce.checked_add(1).ok_or(ExchangeError::Overflow)?,
             utxo: new_utxo,
-            user_action: UserAction::Register(self.game_id.clone(), address.clone()),
+            user_action: action,
         };
 
         Ok((
             new_state,
             (
                 btc_pool.metadata().key_derivation_path.clone(),
-                last_state.utxo.clone(),
+                last_state.map(|s| s.utxo.clone()),
             ),
         ))
     }
 
+    /// Checks that a pool transaction takes nothing in and pays exactly `value` of `coin_id`
+    /// to `to`, the shape of every withdraw and refund of a single gamer.
+    fn ensure_single_payout(
+        input_coins: &[InputCoin],
+        output_coins: &[OutputCoin],
+        coin_id: &CoinId,
+        value: u128,
+        to: &AddressStr,
+        error: fn(String) -> ExchangeError,
+    ) -> Result<()> {
+        match output_coins {
+            [output]
+                if input_coins.is_empty()
+                    && output.coin.id.eq(coin_id)
+                    && output.coin.value == value
+                    && output.to.eq(to) =>
+            {
+                Ok(())
+            }
+            _ => Err(error(format!(
+                "expected {} of {:?} to {}, input_coins: {:?}, output_coins: {:?}",
+                value, coin_id, to, input_coins, output_coins
+            ))),
+        }
+    }
+
     pub fn validate_withdraw(
         &self,
         pool_address: AddressStr,
@@ -373,80 +1166,236 @@ impl Game {
         input_coins: Vec<InputCoin>,
         output_coins: Vec<OutputCoin>,
         initiator_address: AddressStr,
-    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Utxo))> {
-        assert!(
-            matches!(self.game_status, GameStatus::Withdrawing),
-            "GameStatus should be Withdrawing, but got: {:?}",
-            self.game_status
-        );
+    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Option<Utxo>))> {
+        if !matches!(self.game_status, GameStatus::Withdrawing) {
+            return Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Withdrawing,
+                self.game_status.clone(),
+            ));
+        }
+        self.ensure_rune_pool(&pool_address)?;
 
         let rune_info = self
             .rune_info
             .as_ref()
             .ok_or(ExchangeError::RuneNotFound(self.game_name.clone()))?;
 
-        let btc_pool = CookiePools::get(&pool_address)
-            .ok_or(ExchangeError::PoolNotFound(pool_address.clone()))?;
-
-        let last_state = btc_pool.states().last().ok_or(ExchangeError::InvalidState(
-            "Pool has no states".to_string(),
-        ))?;
-
         let gamer = self
-            .gamers
-            .get(&initiator_address)
+            .get_gamer(&initiator_address)
             .ok_or(ExchangeError::GamerNotFound(initiator_address.clone()))?;
 
-        assert!(
-            !gamer.is_withdrawn,
-            "Gamer {} has already withdrawn",
-            initiator_address
-        );
+        if gamer.is_withdrawn {
+            return Err(ExchangeError::GamerWithdrawRepeatedly(
+                initiator_address.clone(),
+            ));
+        }
 
-        let pool_expected_spend_rune = gamer.cookies;
+        Self::ensure_single_payout(
+            &input_coins,
+            &output_coins,
+            &rune_info.rune_id,
+            self.withdrawable_cookies(&gamer)?,
+            &initiator_address,
+            ExchangeError::InvalidWithdrawCoins,
+        )?;
 
-        assert!(
-            output_coins.len() == 1
-                && input_coins.is_empty()
-                && output_coins[0].coin.id.eq(&rune_info.rune_id)
-                && output_coins[0].coin.value == pool_expected_spend_rune
-                && output_coins[0].to.eq(&initiator_address),
-        );
+        self.spend_last_state(
+            pool_address,
+            txid,
+            nonce,
+            pool_utxo_spend,
+            pool_utxo_received,
+            UserAction::Withdraw(self.game_id.clone(), initiator_address),
+        )
+    }
 
-        // check nonce matches
-        (last_state.nonce == nonce)
-            .then(|| ())
-            .ok_or(ExchangeError::PoolStateExpired(last_state.nonce))?;
+    /// Pays out the cookies of many gamers in one pool transaction, one output coin per gamer.
+    /// Anyone may initiate it, since every output has to go to a gamer who has not withdrawn
+    /// yet and carry exactly what that gamer could withdraw alone.
+    pub fn validate_batch_withdraw(
+        &self,
+        pool_address: AddressStr,
+        txid: Txid,
+        nonce: u64,
+        pool_utxo_spend: Vec<String>,
+        pool_utxo_received: Vec<Utxo>,
+        input_coins: Vec<InputCoin>,
+        output_coins: Vec<OutputCoin>,
+    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Option<Utxo>))> {
+        if !matches!(self.game_status, GameStatus::Withdrawing) {
+            return Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Withdrawing,
+                self.game_status.clone(),
+            ));
+        }
+        self.ensure_rune_pool(&pool_address)?;
 
-        (pool_utxo_spend.len() == 1 && pool_utxo_spend.contains(&last_state.utxo.outpoint()))
-            .then(|| ())
-            .ok_or(ExchangeError::InvalidSignPsbtArgs(format!(
-            "Pool Utxo Spend not eq last pool state utxos, pool_utxo_spend: {:?}, last_state: {:?}",
-            pool_utxo_spend, last_state
-        )))?;
+        let rune_info = self
+            .rune_info
+            .as_ref()
+            .ok_or(ExchangeError::RuneNotFound(self.game_name.clone()))?;
 
-        // the pool_utxo_receive should exist
-        let new_utxo = pool_utxo_received.first().map(|s| s.clone()).ok_or(
-            ExchangeError::InvalidSignPsbtArgs("pool_utxo_receive not found".to_string()),
+        if !input_coins.is_empty()
+            || output_coins.is_empty()
+            || output_coins.len() > MAX_BATCH_WITHDRAW_SIZE
+        {
+            return Err(ExchangeError::InvalidWithdrawCoins(format!(
+                "expected 1 to {} output coins and no input coins, input_coins: {:?}, output_coins: {}",
+                MAX_BATCH_WITHDRAW_SIZE,
+                input_coins,
+                output_coins.len()
+            )));
+        }
+
+        // every withdraw changes the shares of the next gamers, settle them in output order
+        let mut settled = self.clone();
+        let mut gamers: Vec<AddressStr> = vec![];
+        let mut pool_expected_spend_rune: u128 = 0;
+        for output in output_coins.iter() {
+            if gamers.contains(&output.to) {
+                return Err(ExchangeError::GamerWithdrawRepeatedly(output.to.clone()));
+            }
+            let mut gamer = self
+                .get_gamer(&output.to)
+                .ok_or(ExchangeError::GamerNotFound(output.to.clone()))?;
+            let cookies = settled.settle_withdraw(&mut gamer)?;
+            (output.coin.id.eq(&rune_info.rune_id) && output.coin.value == cookies)
+                .then(|| ())
+                .ok_or(ExchangeError::InvalidWithdrawCoins(format!(
+                    "expected {} of {:?} to {}, output_coin: {:?}",
+                    cookies, rune_info.rune_id, output.to, output
+                )))?;
+            pool_expected_spend_rune = pool_expected_spend_rune
+                .checked_add(cookies)
+                .ok_or(ExchangeError::Overflow)?;
+            gamers.push(output.to.clone());
+        }
+
+        self.spend_last_state(
+            pool_address,
+            txid,
+            nonce,
+            pool_utxo_spend,
+            pool_utxo_received,
+            UserAction::BatchWithdraw(self.game_id.clone(), gamers),
+        )
+    }
+
+    pub fn validate_creator_withdraw(
+        &self,
+        pool_address: AddressStr,
+        txid: Txid,
+        nonce: u64,
+        pool_utxo_spend: Vec<String>,
+        pool_utxo_received: Vec<Utxo>,
+        input_coins: Vec<InputCoin>,
+        output_coins: Vec<OutputCoin>,
+        initiator_address: AddressStr,
+    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Option<Utxo>))> {
+        if !matches!(self.game_status, GameStatus::Withdrawing) {
+            return Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Withdrawing,
+                self.game_status.clone(),
+            ));
+        }
+        self.ensure_rune_pool(&pool_address)?;
+
+        if initiator_address != self.creator_address {
+            return Err(ExchangeError::NotGameCreator(initiator_address.clone()));
+        }
+
+        if self.is_creator_allocation_withdrawn {
+            return Err(ExchangeError::CreatorWithdrawRepeatedly(
+                self.game_id.clone(),
+            ));
+        }
+
+        let pool_expected_spend_rune = self.creator_allocation();
+        if pool_expected_spend_rune == 0 {
+            return Err(ExchangeError::NoCreatorAllocation(self.game_id.clone()));
+        }
+
+        let rune_info = self
+            .rune_info
+            .as_ref()
+            .ok_or(ExchangeError::RuneNotFound(self.game_name.clone()))?;
+
+        Self::ensure_single_payout(
+            &input_coins,
+            &output_coins,
+            &rune_info.rune_id,
+            pool_expected_spend_rune,
+            &initiator_address,
+            ExchangeError::InvalidWithdrawCoins,
         )?;
 
-        let new_state = CookiePoolState {
-            txid: txid,
-            nonce: last_state
-                .nonce
-                .checked_add(1)
-                .ok_or(ExchangeError::Overflow)?,
-            utxo: new_utxo,
-            user_action: UserAction::Withdraw(self.game_id.clone(), initiator_address.clone()),
-        };
+        self.spend_last_state(
+            pool_address,
+            txid,
+            nonce,
+            pool_utxo_spend,
+            pool_utxo_received,
+            UserAction::CreatorWithdraw(self.game_id.clone()),
+        )
+    }
 
-        return Ok((
-            new_state,
-            (
-                btc_pool.metadata().key_derivation_path.clone(),
-                last_state.utxo.clone(),
-            ),
-        ));
+    pub fn validate_refund(
+        &self,
+        pool_address: AddressStr,
+        txid: Txid,
+        nonce: u64,
+        pool_utxo_spend: Vec<String>,
+        pool_utxo_received: Vec<Utxo>,
+        input_coins: Vec<InputCoin>,
+        output_coins: Vec<OutputCoin>,
+        initiator_address: AddressStr,
+        now: SecondTimestamp,
+    ) -> Result<(CookiePoolState, (Vec<Vec<u8>>, Option<Utxo>))> {
+        if !self.is_refundable(now) {
+            return Err(ExchangeError::GameNotRefundable(self.game_id.clone()));
+        }
+
+        let gamer = self
+            .get_gamer(&initiator_address)
+            .ok_or(ExchangeError::GamerNotFound(initiator_address.clone()))?;
+
+        if gamer.is_refunded {
+            return Err(ExchangeError::GamerRefundRepeatedly(
+                initiator_address.clone(),
+            ));
+        }
+
+        // the fee is paid back from the shard it was paid into
+        let fee_pool_address = self
+            .pool_addresses()
+            .get(gamer.pool_shard as usize)
+            .cloned()
+            .ok_or(ExchangeError::PoolAddressNotFound)?;
+        if pool_address != fee_pool_address {
+            return Err(ExchangeError::PoolAddressMismatch {
+                expected: fee_pool_address,
+                actual: pool_address,
+            });
+        }
+
+        // the pool pays back exactly the register fee to the gamer
+        Self::ensure_single_payout(
+            &input_coins,
+            &output_coins,
+            &CoinId::btc(),
+            self.gamer_register_fee as u128,
+            &initiator_address,
+            ExchangeError::InvalidRefundCoins,
+        )?;
+
+        self.spend_last_state(
+            pool_address,
+            txid,
+            nonce,
+            pool_utxo_spend,
+            pool_utxo_received,
+            UserAction::Refund(self.game_id.clone(), initiator_address),
+        )
     }
 }
 
@@ -458,6 +1407,13 @@ pub struct GameAndGamer {
     pub cookie_amount_per_claim: u128,
     pub claimed_cookies: u128,
     pub gamer: Option<Gamer>,
+    pub game_id: GameId,
+    pub game_name: String,
+    pub game_status: GameStatus,
+    /// The end of the base cooldown, the claim may still be rejected for a hidden jitter after.
+    pub next_claim_time: Option<SecondTimestamp>,
+    /// The cookies the gamer gets when withdrawing, including the unclaimed cookies bonus.
+    pub withdrawable_cookies: Option<u128>,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -467,32 +1423,109 @@ pub enum GameStatus {
     Playing,
     WaitAddedLiquidity,
     Withdrawing,
+    EtchingFailed(String),
+    Cancelled,
+    Paused,
 }
 
 impl GameStatus {
-    pub fn finish_etching(&self) -> GameStatus {
+    pub fn finish_etching(&self) -> Result<GameStatus> {
+        match self {
+            GameStatus::Etching => Ok(GameStatus::Playing),
+            _ => Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Etching,
+                self.clone(),
+            )),
+        }
+    }
+
+    pub fn game_end(&self) -> Result<GameStatus> {
+        match self {
+            GameStatus::Playing | GameStatus::Paused => Ok(GameStatus::WaitAddedLiquidity),
+            _ => Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Playing,
+                self.clone(),
+            )),
+        }
+    }
+
+    pub fn finish_add_liquidity(&self) -> Result<GameStatus> {
+        match self {
+            GameStatus::WaitAddedLiquidity => Ok(GameStatus::Withdrawing),
+            _ => Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::WaitAddedLiquidity,
+                self.clone(),
+            )),
+        }
+    }
+
+    pub fn rollback_add_liquidity(&self) -> Result<GameStatus> {
+        match self {
+            GameStatus::Withdrawing => Ok(GameStatus::WaitAddedLiquidity),
+            _ => Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Withdrawing,
+                self.clone(),
+            )),
+        }
+    }
+
+    pub fn fail_etching(&self, err_info: String) -> Result<GameStatus> {
+        match self {
+            GameStatus::Etching => Ok(GameStatus::EtchingFailed(err_info)),
+            _ => Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Etching,
+                self.clone(),
+            )),
+        }
+    }
+
+    pub fn retry_etching(&self) -> Result<GameStatus> {
+        match self {
+            GameStatus::EtchingFailed(_) => Ok(GameStatus::Etching),
+            _ => Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::EtchingFailed(String::new()),
+                self.clone(),
+            )),
+        }
+    }
+
+    pub fn pause(&self) -> Result<GameStatus> {
         match self {
-            GameStatus::Etching => GameStatus::Playing,
-            _ => panic!("GameStatus should be Etching"),
+            GameStatus::Playing => Ok(GameStatus::Paused),
+            _ => Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Playing,
+                self.clone(),
+            )),
         }
     }
 
-    pub fn game_end(&self) -> GameStatus {
+    pub fn resume(&self) -> Result<GameStatus> {
         match self {
-            GameStatus::Playing => GameStatus::WaitAddedLiquidity,
-            _ => panic!("GameStatus should be Playing"),
+            GameStatus::Paused => Ok(GameStatus::Playing),
+            _ => Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Paused,
+                self.clone(),
+            )),
         }
     }
 
-    pub fn finish_add_liquidity(&self) -> GameStatus {
+    /// A game can be cancelled until it ends. Gamers of a cancelled game
+    /// get their register fee back from the pool.
+    pub fn cancel(&self) -> Result<GameStatus> {
         match self {
-            GameStatus::WaitAddedLiquidity => GameStatus::Withdrawing,
-            _ => panic!("GameStatus should be WaitAddedLiquidity"),
+            GameStatus::Etching
+            | GameStatus::EtchingFailed(_)
+            | GameStatus::Playing
+            | GameStatus::Paused => Ok(GameStatus::Cancelled),
+            _ => Err(ExchangeError::GameStatusNotMatch(
+                GameStatus::Playing,
+                self.clone(),
+            )),
         }
     }
 }
 
-#[derive(CandidType, Deserialize, Serialize, Clone)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Default)]
 pub struct CreateGameArgs {
     pub game_name: String,
     pub gamer_register_fee: Satoshi,
@@ -500,6 +1533,96 @@ pub struct CreateGameArgs {
     pub claim_amount_per_click: u128,
     pub create_address: AddressStr,
     pub rune_premine_amount: u128,
+    pub end_time: Option<SecondTimestamp>,
+    pub unclaimed_cookies_policy: Option<UnclaimedCookiesPolicy>,
+    /// Defaults to `DEFAULT_PLAYER_SHARE_BPS`.
+    pub player_share_bps: Option<u16>,
+    pub creator_share_bps: Option<u16>,
+    pub anti_bot: Option<AntiBotSettings>,
+    /// Defaults to `RewardSchedule::Constant`.
+    pub reward_schedule: Option<RewardSchedule>,
+    /// Cookies out of the player share a gamer gets per referred gamer, none by default.
+    pub referral_bonus: Option<u128>,
+    /// Defaults to a single pool.
+    pub pool_shards: Option<u8>,
+}
+
+impl CreateGameArgs {
+    /// Checks everything about the args that doesn't depend on the other games. The name is
+    /// checked without the surrounding whitespace, which `create_game` trims before storing it.
+    pub fn validate(&self, now: SecondTimestamp) -> Result<()> {
+        let name_length = self.game_name.trim().chars().count();
+        if name_length == 0 {
+            return Err(ExchangeError::InvalidGameArgs(
+                "game name must not be empty".to_string(),
+            ));
+        }
+        if name_length > MAX_GAME_NAME_LENGTH {
+            return Err(ExchangeError::GameNameTooLong(MAX_GAME_NAME_LENGTH));
+        }
+        if self.create_address.is_empty() {
+            return Err(ExchangeError::InvalidGameArgs(
+                "create address must not be empty".to_string(),
+            ));
+        }
+        if self.gamer_register_fee < DUST_BTC_VALUE {
+            return Err(ExchangeError::RegisterFeeTooSmall(
+                self.gamer_register_fee,
+                DUST_BTC_VALUE,
+            ));
+        }
+        if self.claim_amount_per_click == 0 {
+            return Err(ExchangeError::InvalidGameArgs(
+                "claim amount per click must be greater than 0".to_string(),
+            ));
+        }
+        if self.rune_premine_amount <= self.claim_amount_per_click {
+            return Err(ExchangeError::InvalidGameArgs(
+                "rune premine amount must be greater than claim amount per click".to_string(),
+            ));
+        }
+
+        let player_share_bps = self.player_share_bps.unwrap_or(DEFAULT_PLAYER_SHARE_BPS);
+        validate_token_split(player_share_bps, self.creator_share_bps.unwrap_or_default())?;
+
+        let claimable_amount = share_of(self.rune_premine_amount, player_share_bps);
+        if claimable_amount == 0 {
+            return Err(ExchangeError::InvalidGameArgs(
+                "player share of the premine must not be empty".to_string(),
+            ));
+        }
+        self.reward_schedule
+            .clone()
+            .unwrap_or_default()
+            .validate(self.claim_amount_per_click, claimable_amount)?;
+        if self.referral_bonus.unwrap_or_default() > claimable_amount {
+            return Err(ExchangeError::InvalidGameArgs(
+                "referral bonus must not exceed the player share of the premine".to_string(),
+            ));
+        }
+
+        if !(1..=MAX_POOL_SHARDS).contains(&self.pool_shards.unwrap_or(1)) {
+            return Err(ExchangeError::InvalidGameArgs(format!(
+                "pool shards must be between 1 and {}",
+                MAX_POOL_SHARDS
+            )));
+        }
+
+        if self.anti_bot.as_ref().and_then(|s| s.max_claims_per_minute) == Some(0) {
+            return Err(ExchangeError::InvalidGameArgs(
+                "max claims per minute must be greater than 0".to_string(),
+            ));
+        }
+
+        if let Some(end_time) = self.end_time {
+            if end_time <= now {
+                return Err(ExchangeError::InvalidGameArgs(
+                    "end time must be in the future".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[test]
@@ -511,3 +1634,621 @@ pub fn t() {
     let s = String::from_utf8(raw_v.to_vec()).unwrap();
     dbg!(&s);
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn setup_game(game_id: &str, gamers: &[&str]) -> Game {
+        let mut game = Game::new(
+            CreateGameArgs {
+                game_name: format!("game {}", game_id),
+                gamer_register_fee: 1000,
+                claim_cooling_down: 10,
+                claim_amount_per_click: 10,
+                create_address: "creator".to_string(),
+                rune_premine_amount: 1000,
+                ..Default::default()
+            },
+            Principal::anonymous(),
+            game_id.to_string(),
+        );
+        game.game_status = GameStatus::Playing;
+        for gamer in gamers {
+            game.register_new_gamer(gamer.to_string()).unwrap();
+        }
+        game
+    }
+
+    fn game(unclaimed_cookies_policy: UnclaimedCookiesPolicy, cookies: &[(&str, u128)]) -> Game {
+        let mut game = setup_game("unclaimed", &[]);
+        game.unclaimed_cookies_policy = unclaimed_cookies_policy;
+        for (address, amount) in cookies {
+            game.register_new_gamer(address.to_string()).unwrap();
+            game.update_gamer(&address.to_string(), |gamer| gamer.cookies = *amount)
+                .unwrap();
+            game.claimed_cookies += amount;
+        }
+        game
+    }
+
+    #[test]
+    fn test_unclaimed_cookies_are_split_pro_rata() {
+        let mut game = game(
+            UnclaimedCookiesPolicy::DistributeToGamers,
+            &[("alice", 200), ("bob", 100)],
+        );
+        game.end().unwrap();
+
+        // 800 claimable, 300 claimed
+        assert_eq!(game.unclaimed_cookies, 500);
+        let alice = game.get_gamer(&"alice".to_string()).unwrap();
+        let bob = game.get_gamer(&"bob".to_string()).unwrap();
+        assert_eq!(game.unclaimed_cookies_bonus(&alice).unwrap(), 333);
+        assert_eq!(game.unclaimed_cookies_bonus(&bob).unwrap(), 166);
+        assert_eq!(game.withdrawable_cookies(&alice).unwrap(), 533);
+        assert_eq!(game.unclaimed_cookies_to_liquidity, 0);
+        assert_eq!(game.calculate_add_liquidity_rune_amount().unwrap(), 200);
+
+        // the last gamer to withdraw gets the rounding leftover
+        assert_eq!(game.withdraw("alice".to_string()).unwrap(), 533);
+        let bob = game.get_gamer(&"bob".to_string()).unwrap();
+        assert_eq!(game.unclaimed_cookies_bonus(&bob).unwrap(), 167);
+        assert_eq!(game.withdraw("bob".to_string()).unwrap(), 267);
+        assert_eq!(game.unclaimed_cookies_left, 0);
+        assert_eq!(game.cookies_left, 0);
+        // a withdrawn gamer keeps seeing what was paid out
+        let alice = game.get_gamer(&"alice".to_string()).unwrap();
+        assert_eq!(game.withdrawable_cookies(&alice).unwrap(), 533);
+    }
+
+    #[test]
+    fn test_rolled_back_withdraw_returns_the_share() {
+        let mut game = game(
+            UnclaimedCookiesPolicy::DistributeToGamers,
+            &[("alice", 200), ("bob", 100)],
+        );
+        game.end().unwrap();
+        game.withdraw("bob".to_string()).unwrap();
+        assert_eq!(game.unclaimed_cookies_left, 334);
+
+        let mut bob = game.get_gamer(&"bob".to_string()).unwrap();
+        assert_eq!(game.unsettle_withdraw(&mut bob), 266);
+        assert!(!bob.is_withdrawn);
+        assert_eq!(bob.unclaimed_cookies_bonus, 0);
+        assert_eq!(game.unclaimed_cookies_left, 500);
+        assert_eq!(game.cookies_left, 300);
+        assert_eq!(game.unclaimed_cookies_bonus(&bob).unwrap(), 166);
+    }
+
+    #[test]
+    fn test_unclaimed_cookies_go_to_liquidity_by_default() {
+        let mut game = game(UnclaimedCookiesPolicy::AddToLiquidity, &[("alice", 300)]);
+        game.end().unwrap();
+
+        let alice = game.get_gamer(&"alice".to_string()).unwrap();
+        assert_eq!(game.unclaimed_cookies_bonus(&alice).unwrap(), 0);
+        assert_eq!(game.unclaimed_cookies_to_liquidity, 500);
+        assert_eq!(game.calculate_add_liquidity_rune_amount().unwrap(), 700);
+    }
+
+    #[test]
+    fn test_token_split_gives_rounding_leftovers_to_liquidity() {
+        let mut game = setup_game("token_split", &[]);
+        game.rune_premine_amount = 10_007;
+        game.player_share_bps = 7_000;
+        game.creator_share_bps = 1_500;
+
+        assert_eq!(game.claimable_amount(), 7_004);
+        assert_eq!(game.creator_allocation(), 1_501);
+        assert_eq!(game.calculate_add_liquidity_rune_amount().unwrap(), 1_502);
+    }
+
+    fn reward_game(game_id: &str, reward_schedule: RewardSchedule) -> Game {
+        let mut game = setup_game(game_id, &["alice"]);
+        game.rune_premine_amount = 1250;
+        game.claim_amount_per_click = 100;
+        game.reward_schedule = reward_schedule;
+        game
+    }
+
+    #[test]
+    fn test_linear_decay_falls_with_claimed_share() {
+        let mut game = reward_game(
+            "linear_decay",
+            RewardSchedule::LinearDecay { min_amount: 10 },
+        );
+        let alice = game.get_gamer(&"alice".to_string()).unwrap();
+
+        assert_eq!(game.claim_amount(&alice, 0).unwrap(), 100);
+        game.claimed_cookies = 500;
+        assert_eq!(game.claim_amount(&alice, 0).unwrap(), 55);
+        game.claimed_cookies = 995;
+        assert_eq!(game.claim_amount(&alice, 0).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_halving_never_drops_to_zero() {
+        let mut game = reward_game("halving", RewardSchedule::Halving { interval: 2 });
+        let alice = game.get_gamer(&"alice".to_string()).unwrap();
+
+        for (claim_count, amount) in [(0, 100), (1, 100), (2, 50), (5, 25), (1_000, 1)] {
+            game.claim_count = claim_count;
+            assert_eq!(game.claim_amount(&alice, 0).unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn test_streak_bonus_grows_until_capped() {
+        let game = reward_game(
+            "streak",
+            RewardSchedule::Streak {
+                window: 5,
+                bonus_bps_per_click: 1_000,
+                max_bonus_bps: 3_000,
+            },
+        );
+        let mut alice = game.get_gamer(&"alice".to_string()).unwrap();
+        alice.last_click_time = 100;
+
+        // the cooldown of 10 seconds ends at 110, clicks until 115 are on time
+        assert_eq!(game.claim_amount(&alice, 112).unwrap(), 110);
+        alice.streak = 5;
+        assert_eq!(game.claim_amount(&alice, 115).unwrap(), 130);
+        assert_eq!(game.claim_amount(&alice, 116).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_cooldown_jitter_is_not_reported() {
+        let mut game = setup_game("jitter", &["alice"]);
+        game.anti_bot.cooldown_jitter = 30;
+        let mut alice = game.get_gamer(&"alice".to_string()).unwrap();
+        alice.last_click_time = 100;
+
+        // without the salt the whole jitter applies, but only the base cooldown is reported
+        assert!(matches!(
+            game.able_claim(&alice, 111),
+            Err(ExchangeError::GamerCoolingDown(_, 110))
+        ));
+        assert!(game.able_claim(&alice, 140).is_err());
+        assert!(game.able_claim(&alice, 141).is_ok());
+        let info = game.game_and_gamer(Some(alice), 111).unwrap();
+        assert_eq!(info.next_claim_time, Some(110));
+    }
+
+    #[test]
+    fn test_token_split_does_not_overflow() {
+        assert_eq!(share_of(u128::MAX, BPS_DENOMINATOR), u128::MAX);
+        assert_eq!(share_of(u128::MAX, 5_000), u128::MAX / 2);
+        assert_eq!(share_of(u128::MAX, 0), 0);
+    }
+
+    #[test]
+    fn test_validate_token_split() {
+        assert!(validate_token_split(DEFAULT_PLAYER_SHARE_BPS, 0).is_ok());
+        assert!(validate_token_split(8_000, 1_999).is_ok());
+        for (player, creator) in [
+            (0, 0),
+            (8_000, 2_000),
+            (BPS_DENOMINATOR, 0),
+            (u16::MAX, u16::MAX),
+        ] {
+            assert!(matches!(
+                validate_token_split(player, creator),
+                Err(ExchangeError::InvalidTokenSplit(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_creator_allocation_requires_creator_and_ended_game() {
+        let mut game = setup_game("creator_allocation", &[]);
+        game.creator_share_bps = 500;
+        assert!(matches!(
+            game.withdraw_creator_allocation(&"creator".to_string()),
+            Err(ExchangeError::GameNotEnd)
+        ));
+
+        game.game_status = GameStatus::Withdrawing;
+        assert!(matches!(
+            game.withdraw_creator_allocation(&"alice".to_string()),
+            Err(ExchangeError::NotGameCreator(_))
+        ));
+        assert_eq!(
+            game.withdraw_creator_allocation(&"creator".to_string())
+                .unwrap(),
+            50
+        );
+        assert!(matches!(
+            game.withdraw_creator_allocation(&"creator".to_string()),
+            Err(ExchangeError::CreatorWithdrawRepeatedly(_))
+        ));
+    }
+
+    fn create_game_args(
+        claim_amount_per_click: u128,
+        clicks: u128,
+        gamer_register_fee: u64,
+    ) -> CreateGameArgs {
+        CreateGameArgs {
+            game_name: "cookie".to_string(),
+            gamer_register_fee,
+            claim_cooling_down: 10,
+            claim_amount_per_click,
+            create_address: "creator".to_string(),
+            // with the default split the gamers can claim 4/5 of the premine
+            rune_premine_amount: claim_amount_per_click * clicks * 5,
+            ..Default::default()
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_create_game_args_accepts_valid_args(
+            claim_amount_per_click in 1u128..1_000_000_000_000,
+            clicks in 1u128..1_000_000,
+            gamer_register_fee in DUST_BTC_VALUE..u64::MAX,
+        ) {
+            let args = create_game_args(claim_amount_per_click, clicks, gamer_register_fee);
+            prop_assert!(args.validate(0).is_ok());
+
+            let game = Game::new(args, Principal::anonymous(), "valid_args".to_string());
+            prop_assert!(game.calculate_add_liquidity_rune_amount().is_ok());
+        }
+
+        #[test]
+        fn test_create_game_args_never_panics(
+            rune_premine_amount in any::<u128>(),
+            claim_amount_per_click in any::<u128>(),
+            gamer_register_fee in any::<u64>(),
+            player_share_bps in any::<Option<u16>>(),
+            creator_share_bps in any::<Option<u16>>(),
+            end_time in any::<Option<u64>>(),
+            now in any::<u64>(),
+        ) {
+            let args = CreateGameArgs {
+                rune_premine_amount,
+                player_share_bps,
+                creator_share_bps,
+                end_time,
+                ..create_game_args(claim_amount_per_click, 0, gamer_register_fee)
+            };
+            if args.validate(now).is_ok() {
+                let game = Game::new(args, Principal::anonymous(), "any_args".to_string());
+                prop_assert!(game.claimable_amount() > 0);
+                prop_assert!(game.calculate_add_liquidity_rune_amount().is_ok());
+            }
+        }
+
+        #[test]
+        fn test_games_always_end_exactly(
+            claim_amount_per_click in 1u128..1_000,
+            rune_premine_amount in 2u128..20_000,
+            player_share_bps in 1u16..9_000,
+            schedule_kind in 0u8..4,
+            seed in any::<u64>(),
+        ) {
+            let reward_schedule = match schedule_kind {
+                0 => RewardSchedule::Constant,
+                1 => RewardSchedule::LinearDecay {
+                    min_amount: 1 + seed as u128 % claim_amount_per_click,
+                },
+                2 => RewardSchedule::Halving { interval: 1 + seed % 50 },
+                _ => RewardSchedule::Streak {
+                    window: 1 + seed % 10,
+                    bonus_bps_per_click: 1 + (seed % 5_000) as u16,
+                    max_bonus_bps: (seed % 10_001) as u16,
+                },
+            };
+            let args = CreateGameArgs {
+                rune_premine_amount,
+                player_share_bps: Some(player_share_bps),
+                reward_schedule: Some(reward_schedule),
+                ..create_game_args(claim_amount_per_click, 0, DUST_BTC_VALUE)
+            };
+            prop_assume!(args.validate(0).is_ok());
+
+            let mut game = Game::new(args, Principal::anonymous(), "terminate".to_string());
+            let mut gamer = Gamer::new("alice".to_string());
+            let mut clicks = 0u128;
+            while !game.is_end() {
+                // click right after the cooldown, so that streaks keep growing
+                let now = game.claim_deadline(&gamer) + 1;
+                let amount = game.apply_claim(&mut gamer, now).unwrap();
+                prop_assert!(amount > 0);
+                clicks += 1;
+                prop_assert!(clicks <= game.claimable_amount());
+            }
+            prop_assert_eq!(game.claimed_cookies, game.claimable_amount());
+            prop_assert_eq!(gamer.cookies, game.claimable_amount());
+        }
+
+        #[test]
+        fn test_create_game_args_rejects_dust_register_fee(gamer_register_fee in 0..DUST_BTC_VALUE) {
+            prop_assert!(matches!(
+                create_game_args(10, 10, gamer_register_fee).validate(0),
+                Err(ExchangeError::RegisterFeeTooSmall(..))
+            ));
+        }
+
+        #[test]
+        fn test_create_game_args_rejects_long_names(
+            game_name in proptest::string::string_regex(
+                &format!("[a-z]{{{},100}}", MAX_GAME_NAME_LENGTH + 1)
+            ).unwrap(),
+        ) {
+            let args = CreateGameArgs {
+                game_name,
+                ..create_game_args(10, 10, DUST_BTC_VALUE)
+            };
+            prop_assert!(matches!(args.validate(0), Err(ExchangeError::GameNameTooLong(_))));
+        }
+    }
+
+    #[test]
+    fn test_create_game_args_checks_the_trimmed_name() {
+        let name = |length| format!("  {}  ", "a".repeat(length));
+        let args = CreateGameArgs {
+            game_name: name(MAX_GAME_NAME_LENGTH),
+            ..create_game_args(10, 10, DUST_BTC_VALUE)
+        };
+        assert!(args.validate(0).is_ok());
+
+        let args = CreateGameArgs {
+            game_name: name(MAX_GAME_NAME_LENGTH + 1),
+            ..create_game_args(10, 10, DUST_BTC_VALUE)
+        };
+        assert!(matches!(
+            args.validate(0),
+            Err(ExchangeError::GameNameTooLong(_))
+        ));
+    }
+
+    #[test]
+    fn test_create_game_args_rejects_zero_values() {
+        for args in [
+            create_game_args(0, 10, DUST_BTC_VALUE),
+            CreateGameArgs {
+                rune_premine_amount: 0,
+                ..create_game_args(10, 10, DUST_BTC_VALUE)
+            },
+            CreateGameArgs {
+                game_name: "  ".to_string(),
+                ..create_game_args(10, 10, DUST_BTC_VALUE)
+            },
+            CreateGameArgs {
+                player_share_bps: Some(0),
+                ..create_game_args(10, 10, DUST_BTC_VALUE)
+            },
+        ] {
+            assert!(args.validate(0).is_err());
+        }
+    }
+
+    #[test]
+    fn test_create_game_args_allows_indivisible_premine() {
+        let args = CreateGameArgs {
+            rune_premine_amount: 1001,
+            ..create_game_args(7, 0, DUST_BTC_VALUE)
+        };
+        assert!(args.validate(0).is_ok());
+
+        let mut game = Game::new(args, Principal::anonymous(), "indivisible".to_string());
+        let alice = Gamer::new("alice".to_string());
+        game.claimed_cookies = 798;
+        // 800 claimable, the last click only gets the 2 cookies left
+        assert_eq!(game.claim_amount(&alice, 0).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_create_game_args_rejects_past_end_time() {
+        let args = CreateGameArgs {
+            end_time: Some(100),
+            ..create_game_args(10, 10, DUST_BTC_VALUE)
+        };
+        assert!(args.validate(99).is_ok());
+        assert!(matches!(
+            args.validate(100),
+            Err(ExchangeError::InvalidGameArgs(_))
+        ));
+    }
+
+    #[test]
+    fn test_pool_shards_keep_shard_0_key_path() {
+        let mut game = setup_game("shards", &["alice", "bob", "carol"]);
+        game.pool_shards = 3;
+        game.fee_pool_addresses = vec!["fee_1".to_string(), "fee_2".to_string()];
+        game.assign_pool_shard(&"bob".to_string(), 2).unwrap();
+
+        assert_eq!(game.shard_key_path(0), game.key_path());
+        assert_eq!(game.shard_key_path(2), "game_pool_shards_2");
+        assert_eq!(game.pool_shard(&"fee_2".to_string()).unwrap(), 2);
+        assert!(matches!(
+            game.pool_shard(&"other_pool".to_string()),
+            Err(ExchangeError::PoolAddressMismatch { .. })
+        ));
+        assert_eq!(game.shard_register_fees(0).unwrap(), 2000);
+        assert_eq!(game.shard_register_fees(1).unwrap(), 0);
+        assert_eq!(game.shard_register_fees(2).unwrap(), 1000);
+
+        game.assign_pool_shard(&"carol".to_string(), 1).unwrap();
+        game.remove_gamer(&"bob".to_string()).unwrap();
+        assert_eq!(game.fee_shard_gamer_counts, vec![1, 0]);
+        assert_eq!(game.shard_register_fees(0).unwrap(), 1000);
+        assert_eq!(game.shard_register_fees(1).unwrap(), 1000);
+        assert_eq!(game.shard_register_fees(2).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_create_game_args_rejects_invalid_pool_shards() {
+        for pool_shards in [0, MAX_POOL_SHARDS + 1] {
+            let args = CreateGameArgs {
+                pool_shards: Some(pool_shards),
+                ..create_game_args(10, 10, DUST_BTC_VALUE)
+            };
+            assert!(matches!(
+                args.validate(0),
+                Err(ExchangeError::InvalidGameArgs(_))
+            ));
+        }
+        let args = CreateGameArgs {
+            pool_shards: Some(MAX_POOL_SHARDS),
+            ..create_game_args(10, 10, DUST_BTC_VALUE)
+        };
+        assert!(args.validate(0).is_ok());
+    }
+
+    #[test]
+    fn test_single_payout_pays_only_the_initiator() {
+        let coin = |value| ree_exchange_sdk::types::CoinBalance {
+            id: CoinId::btc(),
+            value,
+        };
+        let output = |to: &str, value| OutputCoin {
+            to: to.to_string(),
+            coin: coin(value),
+        };
+        let alice = "alice".to_string();
+        let check = |input_coins: Vec<InputCoin>, output_coins: Vec<OutputCoin>| {
+            Game::ensure_single_payout(
+                &input_coins,
+                &output_coins,
+                &CoinId::btc(),
+                1000,
+                &alice,
+                ExchangeError::InvalidRefundCoins,
+            )
+        };
+
+        assert!(check(vec![], vec![output("alice", 1000)]).is_ok());
+        for output_coins in [
+            vec![],
+            vec![output("bob", 1000)],
+            vec![output("alice", 999)],
+            vec![output("alice", 500), output("alice", 500)],
+        ] {
+            assert!(matches!(
+                check(vec![], output_coins),
+                Err(ExchangeError::InvalidRefundCoins(_))
+            ));
+        }
+        let input = InputCoin {
+            from: "alice".to_string(),
+            coin: coin(1),
+        };
+        assert!(matches!(
+            check(vec![input], vec![output("alice", 1000)]),
+            Err(ExchangeError::InvalidRefundCoins(_))
+        ));
+    }
+
+    #[test]
+    fn test_paused_game_rejects_claims_and_registrations() {
+        let mut game = setup_game("paused", &["alice"]);
+        game.game_status = GameStatus::Paused;
+        let alice = game.get_gamer(&"alice".to_string()).unwrap();
+        let register = |game: &Game| {
+            game.validate_register(
+                "pool".to_string(),
+                Txid::zero(),
+                0,
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                "bob".to_string(),
+                None,
+            )
+        };
+
+        assert!(matches!(
+            game.able_claim(&alice, 100),
+            Err(ExchangeError::GamePaused(_))
+        ));
+        assert!(matches!(register(&game), Err(ExchangeError::GamePaused(_))));
+
+        game.game_status = GameStatus::Playing;
+        assert!(game.able_claim(&alice, 100).is_ok());
+        assert!(!matches!(
+            register(&game),
+            Err(ExchangeError::GamePaused(_))
+        ));
+    }
+
+    #[test]
+    fn test_pause_resume_and_cancel_transitions() {
+        let failed = || GameStatus::EtchingFailed("boom".to_string());
+        assert!(matches!(
+            GameStatus::Playing.pause(),
+            Ok(GameStatus::Paused)
+        ));
+        assert!(matches!(
+            GameStatus::Paused.resume(),
+            Ok(GameStatus::Playing)
+        ));
+        for status in [
+            GameStatus::Etching,
+            failed(),
+            GameStatus::Playing,
+            GameStatus::Paused,
+        ] {
+            assert!(matches!(status.cancel(), Ok(GameStatus::Cancelled)));
+        }
+
+        let ended = [
+            GameStatus::WaitAddedLiquidity,
+            GameStatus::Withdrawing,
+            GameStatus::Cancelled,
+        ];
+        for status in ended.iter().chain(&[GameStatus::Etching, failed()]) {
+            assert!(status.pause().is_err());
+            assert!(status.resume().is_err());
+        }
+        for status in &ended {
+            assert!(status.cancel().is_err());
+        }
+        assert!(GameStatus::Paused.pause().is_err());
+        assert!(GameStatus::Playing.resume().is_err());
+    }
+
+    #[test]
+    fn test_etch_check_backoff_doubles_until_capped() {
+        let mut game = setup_game("etching", &[]);
+        game.game_status = GameStatus::Etching;
+
+        let mut delays = vec![];
+        for _ in 0..8 {
+            game.record_etch_check_failure("pending".to_string(), 1000);
+            delays.push(game.next_etch_check_time - 1000);
+        }
+
+        assert_eq!(delays, vec![120, 240, 480, 960, 1920, 3600, 3600, 3600]);
+        assert_eq!(game.etch_check_count, 8);
+        assert_eq!(game.etch_error, Some("pending".to_string()));
+
+        game.clear_etch_checks();
+        assert_eq!(game.etch_check_count, 0);
+        assert_eq!(game.next_etch_check_time, 0);
+        assert_eq!(game.etch_error, None);
+    }
+
+    #[test]
+    fn test_etch_check_is_due_only_for_etched_games() {
+        let mut game = setup_game("etching", &[]);
+        game.game_status = GameStatus::Etching;
+        // the rune is not etched yet
+        assert!(!game.is_etch_check_due(1000));
+
+        game.etch_rune_commit_tx = "commit".to_string();
+        assert!(game.is_etch_check_due(1000));
+
+        game.record_etch_check_failure("pending".to_string(), 1000);
+        assert!(!game.is_etch_check_due(1119));
+        assert!(game.is_etch_check_due(1120));
+
+        game.game_status = GameStatus::EtchingFailed("failed".to_string());
+        assert!(!game.is_etch_check_due(2000));
+    }
+}
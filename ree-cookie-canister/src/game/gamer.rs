@@ -3,7 +3,6 @@ use std::borrow::Cow;
 use ic_stable_structures::storable::Bound;
 use ic_stable_structures::Storable;
 
-use crate::utils::get_chain_second_timestamp;
 use crate::SecondTimestamp;
 use crate::*;
 use errors::*;
@@ -14,6 +13,58 @@ pub struct Gamer {
     pub cookies: u128,
     pub last_click_time: SecondTimestamp,
     pub is_withdrawn: bool,
+    #[serde(default)]
+    pub register_tx: Option<TxProgress>,
+    #[serde(default)]
+    pub withdraw_tx: Option<TxProgress>,
+    #[serde(default)]
+    pub is_refunded: bool,
+    #[serde(default)]
+    pub refund_tx: Option<TxProgress>,
+    /// Consecutive on-time clicks, see `RewardSchedule::Streak`.
+    #[serde(default)]
+    pub streak: u32,
+    /// The pool shard the register fee was paid into.
+    #[serde(default)]
+    pub pool_shard: u8,
+    /// The share of the unclaimed cookies paid out with the withdraw.
+    #[serde(default)]
+    pub unclaimed_cookies_bonus: u128,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TxStatus {
+    Pending,
+    Confirmed,
+    Final,
+}
+
+/// Tracks a pool transaction from acceptance until it can no longer be rolled back.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TxProgress {
+    pub txid: Txid,
+    pub status: TxStatus,
+    pub finalized_at: Option<SecondTimestamp>,
+}
+
+impl TxProgress {
+    pub fn new(txid: Txid) -> Self {
+        Self {
+            txid,
+            status: TxStatus::Pending,
+            finalized_at: None,
+        }
+    }
+
+    pub fn advance(&mut self, status: TxStatus, now: SecondTimestamp) {
+        if status <= self.status {
+            return;
+        }
+        self.status = status;
+        if status == TxStatus::Final {
+            self.finalized_at = Some(now);
+        }
+    }
 }
 
 impl Storable for Gamer {
@@ -41,15 +92,53 @@ impl Gamer {
             cookies: 0,
             last_click_time: 0,
             is_withdrawn: false,
+            register_tx: None,
+            withdraw_tx: None,
+            is_refunded: false,
+            refund_tx: None,
+            streak: 0,
+            pool_shard: 0,
+            unclaimed_cookies_bonus: 0,
         }
     }
 
-    pub fn claim(&mut self, claimed_cookies: u128) -> Result<u128> {
+    pub fn claim(&mut self, claimed_cookies: u128, now: SecondTimestamp) -> Result<u128> {
         self.cookies = self
             .cookies
             .checked_add(claimed_cookies)
             .ok_or(ExchangeError::Overflow)?;
-        self.last_click_time = get_chain_second_timestamp();
+        self.last_click_time = now;
         Ok(self.cookies)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tx_progress_only_moves_forward() {
+        let mut tx = TxProgress::new(Txid::zero());
+        assert_eq!(tx.status, TxStatus::Pending);
+
+        tx.advance(TxStatus::Confirmed, 10);
+        assert_eq!(tx.status, TxStatus::Confirmed);
+        assert_eq!(tx.finalized_at, None);
+
+        tx.advance(TxStatus::Pending, 20);
+        assert_eq!(tx.status, TxStatus::Confirmed);
+
+        tx.advance(TxStatus::Final, 30);
+        tx.advance(TxStatus::Final, 40);
+        assert_eq!(tx.status, TxStatus::Final);
+        assert_eq!(tx.finalized_at, Some(30));
+    }
+
+    #[test]
+    fn test_tx_progress_can_skip_confirmed() {
+        let mut tx = TxProgress::new(Txid::zero());
+        tx.advance(TxStatus::Final, 10);
+        assert_eq!(tx.status, TxStatus::Final);
+        assert_eq!(tx.finalized_at, Some(10));
+    }
+}
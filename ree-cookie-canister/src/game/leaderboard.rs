@@ -0,0 +1,260 @@
+use std::borrow::Cow;
+
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+
+use crate::exchange::exchange::{
+    GameLeaderboard, GamerStatsMap, Games, GlobalLeaderboard, __CustomStorageAccess,
+};
+use crate::*;
+
+use super::gamer::Gamer;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GamerStats {
+    pub address: AddressStr,
+    /// Cookies claimed in all games together.
+    pub total_cookies: u128,
+    /// Cookies withdrawn from all games together, unclaimed cookie bonuses included.
+    pub total_withdrawn: u128,
+}
+
+impl Storable for GamerStats {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(bincode::serialize(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bincode::serialize_into(&mut bytes, &self).unwrap();
+        bytes
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LeaderboardEntry {
+    pub address: AddressStr,
+    pub cookies: u128,
+}
+
+/// The leaderboard indexes keep `u128::MAX - cookies`, so iterating them in key order
+/// yields the highest balances first.
+fn rank_key(cookies: u128) -> u128 {
+    u128::MAX - cookies
+}
+
+fn set_game_score(game_id: &GameId, address: &AddressStr, old: u128, new: u128) {
+    GameLeaderboard::with_mut(|m| {
+        m.remove(&(game_id.clone(), rank_key(old), address.clone()));
+        if new > 0 {
+            m.insert((game_id.clone(), rank_key(new), address.clone()), ());
+        }
+    });
+}
+
+fn update_gamer_stats<F>(address: &AddressStr, f: F)
+where
+    F: FnOnce(&mut GamerStats),
+{
+    let mut stats = GamerStatsMap::with(|m| m.get(address)).unwrap_or_else(|| GamerStats {
+        address: address.clone(),
+        ..Default::default()
+    });
+    let old_total = stats.total_cookies;
+    f(&mut stats);
+    GlobalLeaderboard::with_mut(|m| {
+        m.remove(&(rank_key(old_total), address.clone()));
+        if stats.total_cookies > 0 {
+            m.insert((rank_key(stats.total_cookies), address.clone()), ());
+        }
+    });
+    GamerStatsMap::with_mut(|m| {
+        m.insert(address.clone(), stats);
+    });
+}
+
+/// Moves a gamer up the leaderboards after claiming `claimed` cookies, `old_cookies`
+/// is the cookie balance of the gamer in this game before the claim.
+pub fn record_claim(game_id: &GameId, address: &AddressStr, old_cookies: u128, claimed: u128) {
+    set_game_score(
+        game_id,
+        address,
+        old_cookies,
+        old_cookies.saturating_add(claimed),
+    );
+    update_gamer_stats(address, |stats| {
+        stats.total_cookies = stats.total_cookies.saturating_add(claimed)
+    });
+}
+
+/// Drops a gamer whose register was rolled back, together with whatever it claimed meanwhile.
+pub fn record_gamer_removed(game_id: &GameId, gamer: &Gamer) {
+    set_game_score(game_id, &gamer.address, gamer.cookies, 0);
+    if gamer.cookies > 0 {
+        update_gamer_stats(&gamer.address, |stats| {
+            stats.total_cookies = stats.total_cookies.saturating_sub(gamer.cookies)
+        });
+    }
+}
+
+pub fn record_withdraw(address: &AddressStr, cookies: u128) {
+    update_gamer_stats(address, |stats| {
+        stats.total_withdrawn = stats.total_withdrawn.saturating_add(cookies)
+    });
+}
+
+pub fn revert_withdraw(address: &AddressStr, cookies: u128) {
+    update_gamer_stats(address, |stats| {
+        stats.total_withdrawn = stats.total_withdrawn.saturating_sub(cookies)
+    });
+}
+
+pub fn game_leaderboard(game_id: &GameId, top_n: u64) -> Vec<LeaderboardEntry> {
+    GameLeaderboard::with(|m| {
+        m.range((game_id.clone(), 0, AddressStr::new())..)
+            .take_while(|e| &e.key().0 == game_id)
+            .take(top_n as usize)
+            .map(|e| {
+                let (_, rank, address) = e.key().clone();
+                LeaderboardEntry {
+                    address,
+                    cookies: u128::MAX - rank,
+                }
+            })
+            .collect()
+    })
+}
+
+pub fn global_leaderboard(top_n: u64) -> Vec<GamerStats> {
+    let addresses: Vec<AddressStr> = GlobalLeaderboard::with(|m| {
+        m.iter()
+            .take(top_n as usize)
+            .map(|e| e.key().1.clone())
+            .collect()
+    });
+    GamerStatsMap::with(|m| {
+        addresses
+            .into_iter()
+            .filter_map(|address| m.get(&address))
+            .collect()
+    })
+}
+
+/// Fills the leaderboards from the gamers stored before they existed, returns the number
+/// of gamers indexed. Does nothing once any gamer has stats.
+pub fn rebuild_leaderboards() -> usize {
+    if !GamerStatsMap::with(|m| m.is_empty()) {
+        return 0;
+    }
+    let games = Games::with(|m| m.iter().map(|e| e.value()).collect::<Vec<_>>());
+    let mut indexed = 0;
+    for game in games.iter() {
+        for gamer in game.gamers() {
+            if gamer.cookies == 0 {
+                continue;
+            }
+            record_claim(&game.game_id, &gamer.address, 0, gamer.cookies);
+            if gamer.is_withdrawn {
+                let withdrawn = game.withdrawable_cookies(&gamer).unwrap_or(gamer.cookies);
+                record_withdraw(&gamer.address, withdrawn);
+            }
+            indexed += 1;
+        }
+    }
+    indexed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::exchange::insert_game;
+    use crate::game::game::{CreateGameArgs, Game};
+
+    fn gamer(address: &str, cookies: u128) -> Gamer {
+        let mut gamer = Gamer::new(address.to_string());
+        gamer.cookies = cookies;
+        gamer
+    }
+
+    fn scores(game_id: &str, top_n: u64) -> Vec<(String, u128)> {
+        game_leaderboard(&game_id.to_string(), top_n)
+            .into_iter()
+            .map(|e| (e.address, e.cookies))
+            .collect()
+    }
+
+    #[test]
+    fn test_game_leaderboard_ranks_by_cookies() {
+        let game_id = "1".to_string();
+        record_claim(&game_id, &"alice".to_string(), 0, 10);
+        record_claim(&game_id, &"bob".to_string(), 0, 10);
+        record_claim(&game_id, &"bob".to_string(), 10, 10);
+        record_claim(&"2".to_string(), &"carol".to_string(), 0, 50);
+
+        assert_eq!(
+            scores("1", 10),
+            vec![("bob".to_string(), 20), ("alice".to_string(), 10)]
+        );
+        assert_eq!(scores("1", 1), vec![("bob".to_string(), 20)]);
+        assert_eq!(scores("2", 10), vec![("carol".to_string(), 50)]);
+    }
+
+    #[test]
+    fn test_global_leaderboard_sums_games() {
+        record_claim(&"1".to_string(), &"alice".to_string(), 0, 10);
+        record_claim(&"2".to_string(), &"alice".to_string(), 0, 30);
+        record_claim(&"2".to_string(), &"bob".to_string(), 0, 20);
+        record_withdraw(&"alice".to_string(), 10);
+
+        let board = global_leaderboard(10);
+        assert_eq!(
+            board
+                .iter()
+                .map(|s| (s.address.as_str(), s.total_cookies, s.total_withdrawn))
+                .collect::<Vec<_>>(),
+            vec![("alice", 40, 10), ("bob", 20, 0)]
+        );
+
+        revert_withdraw(&"alice".to_string(), 10);
+        assert_eq!(global_leaderboard(1)[0].total_withdrawn, 0);
+    }
+
+    #[test]
+    fn test_removed_gamer_leaves_leaderboards() {
+        let game_id = "1".to_string();
+        record_claim(&game_id, &"alice".to_string(), 0, 10);
+        record_claim(&game_id, &"bob".to_string(), 0, 20);
+
+        record_gamer_removed(&game_id, &gamer("bob", 20));
+
+        assert_eq!(scores("1", 10), vec![("alice".to_string(), 10)]);
+        assert_eq!(global_leaderboard(10).len(), 1);
+    }
+    #[test]
+    fn test_rebuild_leaderboards_runs_once() {
+        let mut game = Game::new(
+            CreateGameArgs {
+                game_name: "leaderboard".to_string(),
+                ..Default::default()
+            },
+            Principal::anonymous(),
+            "1".to_string(),
+        );
+        game.register_new_gamer("alice".to_string()).unwrap();
+        game.register_new_gamer("bob".to_string()).unwrap();
+        game.update_gamer(&"alice".to_string(), |gamer| gamer.cookies = 10)
+            .unwrap();
+        insert_game(game);
+
+        // gamers without cookies stay out of the leaderboards
+        assert_eq!(rebuild_leaderboards(), 1);
+        assert_eq!(scores("1", 10), vec![("alice".to_string(), 10)]);
+        assert_eq!(rebuild_leaderboards(), 0);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod game;
 pub mod gamer;
+pub mod leaderboard;
+pub mod referral;
@@ -0,0 +1,223 @@
+use std::borrow::Cow;
+
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+
+use crate::exchange::exchange::{Referrals, __CustomStorageAccess};
+use crate::*;
+use errors::*;
+
+use super::game::{Game, GameStatus, MAX_PAGE_SIZE};
+use super::leaderboard;
+
+/// A gamer who registered in a game on the invitation of another gamer of the same game.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Referral {
+    pub referee: AddressStr,
+    pub referrer: AddressStr,
+    /// The cookies the referrer got, 0 until the register transaction is finalized.
+    pub bonus: u128,
+}
+
+impl Storable for Referral {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(bincode::serialize(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bincode::serialize_into(&mut bytes, &self).unwrap();
+        bytes
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+fn referral_key(game_id: &GameId, referee: &AddressStr) -> (GameId, AddressStr) {
+    (game_id.clone(), referee.clone())
+}
+
+pub fn get_referral(game_id: &GameId, referee: &AddressStr) -> Option<Referral> {
+    Referrals::with(|m| m.get(&referral_key(game_id, referee)))
+}
+
+/// A gamer can only be referred once per game, by another gamer already registered in it.
+pub fn validate_referral(game: &Game, referee: &AddressStr, referrer: &AddressStr) -> Result<()> {
+    if referee == referrer {
+        return Err(ExchangeError::SelfReferral(referee.clone()));
+    }
+    if !game.contains_gamer(referrer) {
+        return Err(ExchangeError::ReferrerNotFound(referrer.clone()));
+    }
+    if get_referral(&game.game_id, referee).is_some() {
+        return Err(ExchangeError::AlreadyReferred(referee.clone()));
+    }
+    Ok(())
+}
+
+pub fn record_referral(game: &Game, referee: &AddressStr, referrer: &AddressStr) -> Result<()> {
+    validate_referral(game, referee, referrer)?;
+    Referrals::with_mut(|m| {
+        m.insert(
+            referral_key(&game.game_id, referee),
+            Referral {
+                referee: referee.clone(),
+                referrer: referrer.clone(),
+                bonus: 0,
+            },
+        );
+    });
+    Ok(())
+}
+
+/// Drops the referral of a gamer whose register was rolled back, before any bonus was paid.
+pub fn remove_referral(game_id: &GameId, referee: &AddressStr) -> Option<Referral> {
+    Referrals::with_mut(|m| m.remove(&referral_key(game_id, referee)))
+}
+
+/// Pays the referral bonus of `referee` once its register can no longer be rolled back.
+/// The bonus comes out of the cookies left to claim, so it shrinks towards the end of the
+/// game and is skipped once the game stopped playing. Returns the cookies paid.
+pub fn pay_referral_bonus(game: &mut Game, referee: &AddressStr) -> Result<u128> {
+    let Some(mut referral) = get_referral(&game.game_id, referee) else {
+        return Ok(0);
+    };
+    if referral.bonus > 0 || !matches!(game.game_status, GameStatus::Playing) {
+        return Ok(0);
+    }
+    let Some(mut referrer) = game.get_gamer(&referral.referrer) else {
+        return Ok(0);
+    };
+    let remaining = game
+        .claimable_amount()
+        .checked_sub(game.claimed_cookies)
+        .ok_or(ExchangeError::Overflow)?;
+    let bonus = game.referral_bonus.min(remaining);
+    if bonus == 0 {
+        return Ok(0);
+    }
+
+    game.claimed_cookies = game
+        .claimed_cookies
+        .checked_add(bonus)
+        .ok_or(ExchangeError::Overflow)?;
+    let old_cookies_balance = referrer.cookies;
+    referrer.cookies = referrer
+        .cookies
+        .checked_add(bonus)
+        .ok_or(ExchangeError::Overflow)?;
+    game.save_gamer(referrer);
+    leaderboard::record_claim(
+        &game.game_id,
+        &referral.referrer,
+        old_cookies_balance,
+        bonus,
+    );
+
+    referral.bonus = bonus;
+    Referrals::with_mut(|m| {
+        m.insert(referral_key(&game.game_id, referee), referral);
+    });
+
+    if game.is_end() {
+        game.end()?;
+    }
+    Ok(bonus)
+}
+
+/// The referral graph of a game as edges from referee to referrer, ordered by referee.
+pub fn referrals_page(game_id: &GameId, offset: u64, limit: u64) -> Vec<Referral> {
+    Referrals::with(|m| {
+        m.range((game_id.clone(), AddressStr::new())..)
+            .take_while(|e| &e.key().0 == game_id)
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|e| e.value())
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::game::CreateGameArgs;
+
+    fn game(game_id: &str, referral_bonus: u128, gamers: &[&str]) -> Game {
+        let mut game = Game::new(
+            CreateGameArgs {
+                game_name: "referral".to_string(),
+                gamer_register_fee: DUST_BTC_VALUE,
+                claim_cooling_down: 10,
+                claim_amount_per_click: 10,
+                create_address: "creator".to_string(),
+                rune_premine_amount: 1000,
+                referral_bonus: Some(referral_bonus),
+                ..Default::default()
+            },
+            Principal::anonymous(),
+            game_id.to_string(),
+        );
+        game.game_status = GameStatus::Playing;
+        for gamer in gamers {
+            game.register_new_gamer(gamer.to_string()).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn test_referral_rejects_self_unknown_and_repeated() {
+        let game = game("1", 50, &["alice", "bob"]);
+        let alice = "alice".to_string();
+        let carol = "carol".to_string();
+
+        assert!(matches!(
+            validate_referral(&game, &carol, &carol),
+            Err(ExchangeError::SelfReferral(_))
+        ));
+        assert!(matches!(
+            validate_referral(&game, &carol, &"dave".to_string()),
+            Err(ExchangeError::ReferrerNotFound(_))
+        ));
+
+        record_referral(&game, &carol, &alice).unwrap();
+        assert!(matches!(
+            record_referral(&game, &carol, &"bob".to_string()),
+            Err(ExchangeError::AlreadyReferred(_))
+        ));
+        assert_eq!(referrals_page(&game.game_id, 0, 10).len(), 1);
+    }
+
+    #[test]
+    fn test_referral_bonus_is_paid_once_from_the_player_share() {
+        let mut game = game("2", 50, &["alice"]);
+        let alice = "alice".to_string();
+        let carol = "carol".to_string();
+        record_referral(&game, &carol, &alice).unwrap();
+        game.claimed_cookies = game.claimable_amount() - 30;
+
+        assert_eq!(pay_referral_bonus(&mut game, &carol).unwrap(), 30);
+        assert_eq!(pay_referral_bonus(&mut game, &carol).unwrap(), 0);
+        assert_eq!(game.get_gamer(&alice).unwrap().cookies, 30);
+        assert_eq!(game.claimed_cookies, game.claimable_amount());
+        assert_eq!(get_referral(&game.game_id, &carol).unwrap().bonus, 30);
+    }
+
+    #[test]
+    fn test_referral_graph_is_kept_per_game() {
+        let first = game("3", 0, &["alice"]);
+        let second = game("4", 0, &["bob"]);
+        let carol = "carol".to_string();
+
+        record_referral(&first, &carol, &"alice".to_string()).unwrap();
+        record_referral(&second, &carol, &"bob".to_string()).unwrap();
+        assert_eq!(referrals_page(&first.game_id, 0, 10)[0].referrer, "alice");
+        assert_eq!(referrals_page(&second.game_id, 0, 10)[0].referrer, "bob");
+
+        remove_referral(&first.game_id, &carol);
+        assert!(referrals_page(&first.game_id, 0, 10).is_empty());
+    }
+}
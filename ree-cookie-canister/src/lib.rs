@@ -1,5 +1,9 @@
+pub mod anti_bot;
 pub mod canister;
+pub mod config;
+pub mod deadline;
 pub mod errors;
+pub mod etching;
 pub mod exchange;
 pub mod external;
 pub mod game;
@@ -14,6 +18,10 @@ pub use external::rune_indexer::{RuneEntry, Service as RuneIndexer};
 use game::game::CreateGameArgs;
 use game::game::Game;
 use game::game::GameAndPool;
+use game::game::{GameAndGamer, GameSummary, ListGamesArgs};
+use game::gamer::Gamer;
+use game::leaderboard::{GamerStats, LeaderboardEntry};
+use game::referral::Referral;
 pub use ic_canister_log::log;
 pub use ic_stable_structures::StableBTreeMap;
 pub use log::*;
@@ -24,6 +32,7 @@ use ree_exchange_sdk::{
 pub use serde::{Deserialize, Serialize};
 use state::ExchangeState;
 use utils::{AddLiquidityInfo, RuneCommitList};
+use config::{CanisterConfig, CookieArgs, UpgradeArgs};
 use external::etch_canister::{EtchingArgs, SendEtchingInfo};
 
 pub const SIWB_TESTNET_CANISTER: &'static str = "stxih-wyaaa-aaaah-aq2la-cai";
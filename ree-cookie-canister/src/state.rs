@@ -3,12 +3,16 @@ use ic_stable_structures::Storable;
 use std::borrow::Cow;
 use std::collections::HashMap;
 
-use crate::game::game::Game;
+use crate::game::game::{Game, GameStatus, RuneInfo, DEFAULT_PLAYER_SHARE_BPS};
+use crate::game::gamer::Gamer;
 use crate::*;
+use ic_cdk::api::management_canister::bitcoin::Satoshi;
 
 #[derive(Deserialize, Serialize, Clone, CandidType, Default)]
 pub struct ExchangeState {
-    pub games: HashMap<GameId, Game>,
+    /// Games stored by versions before the per-key game maps were introduced.
+    /// Drained into `Games` and `Gamers` by `post_upgrade`, always empty afterwards.
+    pub games: HashMap<GameId, LegacyGame>,
     pub txid_game_map: HashMap<String, GameId>,
 }
 
@@ -38,6 +42,102 @@ impl ExchangeState {
     }
 }
 
+/// The layout of `Game` when all gamers were kept inline in the state cell.
+#[derive(Deserialize, Serialize, Clone, CandidType)]
+pub struct LegacyGame {
+    pub game_id: GameId,
+    pub game_name: String,
+    pub gamer_register_fee: Satoshi,
+    pub claim_cooling_down: Seconds,
+    pub claim_amount_per_click: u128,
+    pub game_status: GameStatus,
+    pub creator: Principal,
+    pub creator_address: AddressStr,
+    pub pool_address: Option<AddressStr>,
+    pub rune_premine_amount: u128,
+    pub rune_info: Option<RuneInfo>,
+    pub claimed_cookies: u128,
+    pub gamers: HashMap<AddressStr, LegacyGamer>,
+    #[serde(default)]
+    pub etch_rune_commit_tx: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, CandidType)]
+pub struct LegacyGamer {
+    pub address: String,
+    pub cookies: u128,
+    pub last_click_time: SecondTimestamp,
+    pub is_withdrawn: bool,
+}
+
+impl From<LegacyGamer> for Gamer {
+    fn from(legacy: LegacyGamer) -> Self {
+        Gamer {
+            address: legacy.address,
+            cookies: legacy.cookies,
+            last_click_time: legacy.last_click_time,
+            is_withdrawn: legacy.is_withdrawn,
+            register_tx: None,
+            withdraw_tx: None,
+            is_refunded: false,
+            refund_tx: None,
+            streak: 0,
+            pool_shard: 0,
+            unclaimed_cookies_bonus: 0,
+        }
+    }
+}
+
+impl LegacyGame {
+    pub fn into_game_and_gamers(self) -> (Game, Vec<Gamer>) {
+        let gamers = self
+            .gamers
+            .into_values()
+            .map(Gamer::from)
+            .collect::<Vec<_>>();
+        let game = Game {
+            game_id: self.game_id,
+            game_name: self.game_name,
+            gamer_register_fee: self.gamer_register_fee,
+            claim_cooling_down: self.claim_cooling_down,
+            claim_amount_per_click: self.claim_amount_per_click,
+            game_status: self.game_status,
+            creator: self.creator,
+            creator_address: self.creator_address,
+            pool_address: self.pool_address,
+            rune_premine_amount: self.rune_premine_amount,
+            rune_info: self.rune_info,
+            claimed_cookies: self.claimed_cookies,
+            gamer_count: gamers.len() as u64,
+            etch_rune_commit_tx: self.etch_rune_commit_tx,
+            etch_check_count: 0,
+            next_etch_check_time: 0,
+            etch_error: None,
+            add_liquidity_tx: None,
+            last_active_time: 0,
+            end_time: None,
+            unclaimed_cookies_policy: Default::default(),
+            unclaimed_cookies: 0,
+            unclaimed_cookies_to_liquidity: 0,
+            unclaimed_cookies_left: 0,
+            cookies_left: 0,
+            player_share_bps: DEFAULT_PLAYER_SHARE_BPS,
+            creator_share_bps: 0,
+            is_creator_allocation_withdrawn: false,
+            creator_withdraw_tx: None,
+            anti_bot: Default::default(),
+            reward_schedule: Default::default(),
+            claim_count: 0,
+            referral_bonus: 0,
+            pool_shards: 1,
+            fee_pool_addresses: vec![],
+            fee_shards_with_liquidity: vec![],
+            fee_shard_gamer_counts: vec![],
+        };
+        (game, gamers)
+    }
+}
+
 #[test]
 pub fn test() {
     let input = "225; 209; 222; 36; 248; 96; 118; 238; 2; 172; 201; 226; 207; 83; 78; 83; 28; 133; 229; 192; 29; 162; 40; 195; 199; 202; 155; 62; 2";
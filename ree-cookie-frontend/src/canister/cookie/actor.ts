@@ -23,11 +23,18 @@ export function cookieActorWithIdentity(
   })
 }
 
-export type GameStatusStr = "Etching" | "Playing" | "WaitAddedLiquidity" | "Withdrawing"
+export type GameStatusStr =
+  | "Etching"
+  | "EtchingFailed"
+  | "Playing"
+  | "Paused"
+  | "Cancelled"
+  | "WaitAddedLiquidity"
+  | "Withdrawing"
 
 export function game_status_str(game_status: GameStatus): GameStatusStr {
   let s = Object.entries(game_status)[0]![0];
-  if(["Etching", "Playing", "WaitAddedLiquidity", "Withdrawing"].includes(s)) {
+  if(["Etching", "EtchingFailed", "Playing", "Paused", "Cancelled", "WaitAddedLiquidity", "Withdrawing"].includes(s)) {
     return s as GameStatusStr;
   } else {
     throw new Error(`Invalid game status: ${s}`);
@@ -6,7 +6,28 @@ export interface AddLiquidityInfo {
   'btc_amount_for_add_liquidity' : bigint,
   'rune_amount_for_add_liquidity' : bigint,
 }
+export interface AntiBotSettings {
+  'max_claims_per_minute' : [] | [number],
+  'require_challenge' : boolean,
+  'cooldown_jitter' : bigint,
+}
+export type BitcoinNetwork = { 'Mainnet' : null } |
+  { 'Testnet4' : null } |
+  { 'Regtest' : null };
+export interface CanisterConfig {
+  'icp_ledger_canister' : Principal,
+  'etch_canister' : Principal,
+  'network' : BitcoinNetwork,
+  'rune_indexer_canister' : Principal,
+  'orchestrator_canister' : Principal,
+  'siwb_canister' : Principal,
+}
+export type ClaimViolation = { 'ChallengeMismatch' : null } |
+  { 'ChallengeMissing' : null } |
+  { 'RateLimited' : bigint };
 export interface CoinBalance { 'id' : string, 'value' : bigint }
+export type CookieArgs = { 'Upgrade' : [] | [UpgradeArgs] } |
+  { 'Init' : InitArgs };
 export interface CookiePoolState {
   'txid' : string,
   'utxo' : Utxo,
@@ -14,11 +35,19 @@ export interface CookiePoolState {
   'nonce' : bigint,
 }
 export interface CreateGameArgs {
+  'reward_schedule' : [] | [RewardSchedule],
+  'player_share_bps' : [] | [number],
   'rune_premine_amount' : bigint,
   'create_address' : string,
+  'end_time' : [] | [bigint],
+  'creator_share_bps' : [] | [number],
   'claim_amount_per_click' : bigint,
   'game_name' : string,
+  'anti_bot' : [] | [AntiBotSettings],
+  'unclaimed_cookies_policy' : [] | [UnclaimedCookiesPolicy],
+  'referral_bonus' : [] | [bigint],
   'claim_cooling_down' : bigint,
+  'pool_shards' : [] | [number],
   'gamer_register_fee' : bigint,
 }
 export interface EtchingArgs {
@@ -32,38 +61,58 @@ export interface EtchingArgs {
   'symbol' : [] | [string],
 }
 export type ExchangeError = { 'InvalidSignPsbtArgs' : string } |
+  { 'ClaimRejected' : ClaimViolation } |
+  { 'InvalidTokenSplit' : string } |
   { 'InvalidNumeric' : null } |
   { 'ParseUtxoRuneBalanceError' : string } |
   { 'Overflow' : null } |
+  { 'InvalidActionParams' : string } |
+  { 'GamePaused' : string } |
   { 'InvalidInput' : null } |
   { 'PoolAddressNotFound' : null } |
   { 'NatConvertError' : bigint } |
+  { 'NotGameCreator' : string } |
   { 'PoolNotFound' : string } |
   { 'RuneNotFound' : string } |
+  { 'NoCreatorAllocation' : string } |
+  { 'InvalidRefundCoins' : string } |
   { 'CookieBalanceInsufficient' : bigint } |
+  { 'SelfReferral' : string } |
+  { 'TooManyGames' : bigint } |
   { 'GameEnd' : null } |
   { 'GamerAlreadyExist' : string } |
   { 'DuplicateBlock' : [number, string] } |
   { 'PoolStateExpired' : bigint } |
+  { 'InvalidGameArgs' : string } |
   { 'GamerNotFound' : string } |
+  { 'GameNameTooLong' : bigint } |
   { 'GameStatusNotMatch' : [GameStatus, GameStatus] } |
   { 'GameNotEnd' : null } |
+  { 'CreatorWithdrawRepeatedly' : string } |
   { 'TooSmallFunds' : null } |
   { 'Unrecoverable' : null } |
   { 'LastStateNotFound' : null } |
   { 'InvalidRuneId' : null } |
+  { 'ReferrerNotFound' : string } |
+  { 'AlreadyReferred' : string } |
+  { 'CallerNotAddressOwner' : string } |
   { 'InvalidPool' : null } |
   { 'InvalidPsbt' : string } |
   { 'GameNotFound' : string } |
   { 'PoolAlreadyExists' : null } |
   { 'GamerCoolingDown' : [string, bigint] } |
   { 'InvalidTxid' : string } |
+  { 'GameNameAlreadyExist' : string } |
+  { 'GameNotRefundable' : string } |
   { 'InvalidLiquidity' : null } |
+  { 'FetchPrincipalError' : string } |
   { 'DepositRuneBalanceIncorrect' : [string, string] } |
   { 'EmptyPool' : null } |
   { 'RuneIndexerResultError' : string } |
+  { 'RegisterFeeTooSmall' : [bigint, bigint] } |
   { 'LpNotFound' : null } |
   { 'ChainKeyError' : null } |
+  { 'InvalidWithdrawCoins' : string } |
   { 'FetchRuneIndexerError' : [RejectionCode, string] } |
   { 'CustomError' : string } |
   { 'InvalidState' : string } |
@@ -71,11 +120,8 @@ export type ExchangeError = { 'InvalidSignPsbtArgs' : string } |
   { 'InsufficientFunds' : null } |
   { 'GamerWithdrawRepeatedly' : string } |
   { 'RuneIdNotMatch' : [string, string] } |
+  { 'GamerRefundRepeatedly' : string } |
   { 'PoolAddressMismatch' : { 'actual' : string, 'expected' : string } };
-export interface ExchangeState {
-  'txid_game_map' : Array<[string, string]>,
-  'games' : Array<[string, Game]>,
-}
 export interface ExecuteTxArgs {
   'zero_confirmed_tx_queue_length' : number,
   'txid' : string,
@@ -84,19 +130,55 @@ export interface ExecuteTxArgs {
   'psbt_hex' : string,
 }
 export interface Game {
+  'reward_schedule' : RewardSchedule,
+  'fee_pool_addresses' : Array<string>,
+  'etch_check_count' : number,
   'creator' : Principal,
+  'player_share_bps' : number,
   'claimed_cookies' : bigint,
+  'unclaimed_cookies_left' : bigint,
   'rune_premine_amount' : bigint,
+  'creator_withdraw_tx' : [] | [TxProgress],
+  'add_liquidity_tx' : [] | [TxProgress],
   'creator_address' : string,
   'rune_info' : [] | [RuneInfo],
-  'gamers' : Array<[string, Gamer]>,
+  'fee_shards_with_liquidity' : Array<string>,
+  'claim_count' : bigint,
+  'fee_shard_gamer_counts' : Array<bigint>,
+  'end_time' : [] | [bigint],
+  'creator_share_bps' : number,
   'claim_amount_per_click' : bigint,
   'game_id' : string,
   'game_status' : GameStatus,
+  'unclaimed_cookies' : bigint,
   'game_name' : string,
   'etch_rune_commit_tx' : string,
+  'anti_bot' : AntiBotSettings,
+  'cookies_left' : bigint,
+  'unclaimed_cookies_policy' : UnclaimedCookiesPolicy,
+  'last_active_time' : bigint,
+  'referral_bonus' : bigint,
   'pool_address' : [] | [string],
   'claim_cooling_down' : bigint,
+  'next_etch_check_time' : bigint,
+  'gamer_count' : bigint,
+  'etch_error' : [] | [string],
+  'pool_shards' : number,
+  'is_creator_allocation_withdrawn' : boolean,
+  'unclaimed_cookies_to_liquidity' : bigint,
+  'gamer_register_fee' : bigint,
+}
+export interface GameAndGamer {
+  'withdrawable_cookies' : [] | [bigint],
+  'claimed_cookies' : bigint,
+  'cookie_amount_per_claim' : bigint,
+  'is_end' : boolean,
+  'game_id' : string,
+  'game_status' : GameStatus,
+  'gamer' : [] | [Gamer],
+  'game_name' : string,
+  'next_claim_time' : [] | [bigint],
+  'claim_cooling_down' : bigint,
   'gamer_register_fee' : bigint,
 }
 export interface GameAndPool {
@@ -104,17 +186,60 @@ export interface GameAndPool {
   'pool_state' : [] | [CookiePoolState],
   'pool_metadata' : [] | [Metadata],
 }
-export type GameStatus = { 'WaitAddedLiquidity' : null } |
+export type GameSort = { 'MostClaimed' : null } |
+  { 'Oldest' : null } |
+  { 'MostGamers' : null } |
+  { 'Newest' : null };
+export type GameStatus = { 'Paused' : null } |
+  { 'WaitAddedLiquidity' : null } |
   { 'Playing' : null } |
   { 'Withdrawing' : null } |
+  { 'EtchingFailed' : string } |
+  { 'Cancelled' : null } |
   { 'Etching' : null };
+export interface GameSummary {
+  'creator' : Principal,
+  'claimed_cookies' : bigint,
+  'creator_address' : string,
+  'rune_info' : [] | [RuneInfo],
+  'end_time' : [] | [bigint],
+  'claim_amount_per_click' : bigint,
+  'claimable_amount' : bigint,
+  'game_id' : string,
+  'game_status' : GameStatus,
+  'game_name' : string,
+  'pool_address' : [] | [string],
+  'claim_cooling_down' : bigint,
+  'gamer_count' : bigint,
+  'gamer_register_fee' : bigint,
+}
 export interface Gamer {
   'is_withdrawn' : boolean,
+  'streak' : number,
+  'register_tx' : [] | [TxProgress],
   'last_click_time' : bigint,
+  'pool_shard' : number,
+  'refund_tx' : [] | [TxProgress],
+  'withdraw_tx' : [] | [TxProgress],
   'address' : string,
   'cookies' : bigint,
+  'unclaimed_cookies_bonus' : bigint,
+  'is_refunded' : boolean,
+}
+export interface GamerStats {
+  'total_cookies' : bigint,
+  'address' : string,
+  'total_withdrawn' : bigint,
 }
 export interface GetPoolInfoArgs { 'pool_address' : string }
+export interface InitArgs {
+  'icp_ledger_canister' : [] | [Principal],
+  'etch_canister' : [] | [Principal],
+  'network' : BitcoinNetwork,
+  'rune_indexer_canister' : [] | [Principal],
+  'orchestrator_canister' : [] | [Principal],
+  'siwb_canister' : [] | [Principal],
+}
 export interface InputCoin { 'coin' : CoinBalance, 'from' : string }
 export interface Intention {
   'input_coins' : Array<InputCoin>,
@@ -132,6 +257,14 @@ export interface IntentionSet {
   'initiator_address' : string,
   'intentions' : Array<Intention>,
 }
+export interface LeaderboardEntry { 'address' : string, 'cookies' : bigint }
+export interface ListGamesArgs {
+  'creator_filter' : [] | [Principal],
+  'status_filter' : [] | [GameStatus],
+  'sort' : [] | [GameSort],
+  'offset' : bigint,
+  'limit' : bigint,
+}
 export interface LogoParams {
   'content_type' : string,
   'content_base64' : string,
@@ -167,6 +300,11 @@ export interface PoolInfo {
   'nonce' : bigint,
   'utxos' : Array<Utxo>,
 }
+export interface Referral {
+  'referrer' : string,
+  'bonus' : bigint,
+  'referee' : string,
+}
 export type RejectionCode = { 'NoError' : null } |
   { 'CanisterError' : null } |
   { 'SysTransient' : null } |
@@ -178,14 +316,60 @@ export type Result = { 'Ok' : bigint } |
   { 'Err' : ExchangeError };
 export type Result_1 = { 'Ok' : string } |
   { 'Err' : string };
+export type Result_10 = { 'Ok' : Array<[string, Result]> } |
+  { 'Err' : ExchangeError };
 export type Result_2 = { 'Ok' : null } |
   { 'Err' : string };
+export type Result_3 = { 'Ok' : AddLiquidityInfo } |
+  { 'Err' : string };
+export type Result_4 = { 'Ok' : Array<Gamer> } |
+  { 'Err' : string };
+export type Result_5 = { 'Ok' : GameAndGamer } |
+  { 'Err' : string };
+export type Result_6 = { 'Ok' : Array<GameAndGamer> } |
+  { 'Err' : string };
+export type Result_7 = { 'Ok' : Array<LeaderboardEntry> } |
+  { 'Err' : string };
+export type Result_8 = { 'Ok' : bigint } |
+  { 'Err' : ExchangeError };
+export type Result_9 = { 'Ok' : Array<Referral> } |
+  { 'Err' : string };
+export type RewardSchedule = { 'LinearDecay' : { 'min_amount' : bigint } } |
+  {
+    'Streak' : {
+      'max_bonus_bps' : number,
+      'window' : bigint,
+      'bonus_bps_per_click' : number,
+    }
+  } |
+  { 'Constant' : null } |
+  { 'Halving' : { 'interval' : bigint } };
 export interface RollbackTxArgs { 'txid' : string, 'reason_code' : string }
 export interface RuneInfo { 'rune_name' : string, 'rune_id' : string }
+export interface TxProgress {
+  'status' : TxStatus,
+  'txid' : string,
+  'finalized_at' : [] | [bigint],
+}
+export type TxStatus = { 'Confirmed' : null } |
+  { 'Final' : null } |
+  { 'Pending' : null };
+export type UnclaimedCookiesPolicy = { 'DistributeToGamers' : null } |
+  { 'AddToLiquidity' : null };
+export interface UpgradeArgs {
+  'icp_ledger_canister' : [] | [Principal],
+  'etch_canister' : [] | [Principal],
+  'rune_indexer_canister' : [] | [Principal],
+  'orchestrator_canister' : [] | [Principal],
+  'siwb_canister' : [] | [Principal],
+}
 export type UserAction = { 'Withdraw' : [string, string] } |
   { 'AddLiquidity' : null } |
   { 'Init' : null } |
-  { 'Register' : [string, string] };
+  { 'Refund' : [string, string] } |
+  { 'BatchWithdraw' : [string, Array<string>] } |
+  { 'Register' : [string, string] } |
+  { 'CreatorWithdraw' : string };
 export interface Utxo {
   'coins' : Array<CoinBalance>,
   'sats' : bigint,
@@ -193,23 +377,38 @@ export interface Utxo {
   'vout' : number,
 }
 export interface _SERVICE {
-  'claim' : ActorMethod<[string], Result>,
+  'cancel_game' : ActorMethod<[string], Result_2>,
+  'claim' : ActorMethod<[string, [] | [bigint]], Result>,
+  'claim_many' : ActorMethod<[Array<string>], Result_10>,
   'create_game' : ActorMethod<[CreateGameArgs], Result_1>,
   'etch' : ActorMethod<[EtchingArgs], Result_1>,
   'etch_rune' : ActorMethod<[string, string], Result_1>,
   'execute_tx' : ActorMethod<[ExecuteTxArgs], Result_1>,
   'finalize_etch' : ActorMethod<[string], Result_1>,
   'game_address' : ActorMethod<[string], Result_1>,
-  'get_exchange_state' : ActorMethod<[], ExchangeState>,
+  'get_claim_challenge' : ActorMethod<[string], Result_8>,
+  'get_config' : ActorMethod<[], CanisterConfig>,
   'get_game_info' : ActorMethod<[string], [] | [GameAndPool]>,
   'get_game_pool_address' : ActorMethod<[string], string>,
+  'get_gamer_info' : ActorMethod<[string, string], Result_5>,
   'get_games_info' : ActorMethod<[], Array<GameAndPool>>,
+  'get_global_leaderboard' : ActorMethod<[bigint], Array<GamerStats>>,
+  'get_leaderboard' : ActorMethod<[string, bigint], Result_7>,
+  'get_my_games' : ActorMethod<[], Result_6>,
   'get_pool_info' : ActorMethod<[GetPoolInfoArgs], [] | [PoolInfo]>,
   'get_pool_list' : ActorMethod<[], Array<PoolBasic>>,
+  'get_referrals' : ActorMethod<[string, bigint, bigint], Result_9>,
+  'list_gamers' : ActorMethod<[string, bigint, bigint], Result_4>,
+  'list_games' : ActorMethod<[ListGamesArgs], Array<GameSummary>>,
   'new_block' : ActorMethod<[NewBlockInfo], Result_2>,
-  'query_add_liquidity_info' : ActorMethod<[string], AddLiquidityInfo>,
+  'pause_game' : ActorMethod<[string], Result_2>,
+  'query_add_liquidity_info' : ActorMethod<[string, [] | [number]], Result_3>,
   'query_etching_list' : ActorMethod<[string], Array<string>>,
+  'resume_game' : ActorMethod<[string], Result_2>,
+  'retry_etch_rune' : ActorMethod<[string, string], Result_1>,
   'rollback_tx' : ActorMethod<[RollbackTxArgs], Result_2>,
+  'route_register_pool' : ActorMethod<[string], Result_1>,
+  'update_config' : ActorMethod<[UpgradeArgs], Result_2>,
 }
 export declare const idlFactory: IDL.InterfaceFactory;
 export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
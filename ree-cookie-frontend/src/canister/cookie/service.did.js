@@ -1,8 +1,41 @@
 export const idlFactory = ({ IDL }) => {
+  const UpgradeArgs = IDL.Record({
+    'icp_ledger_canister' : IDL.Opt(IDL.Principal),
+    'etch_canister' : IDL.Opt(IDL.Principal),
+    'rune_indexer_canister' : IDL.Opt(IDL.Principal),
+    'orchestrator_canister' : IDL.Opt(IDL.Principal),
+    'siwb_canister' : IDL.Opt(IDL.Principal),
+  });
+  const BitcoinNetwork = IDL.Variant({
+    'Mainnet' : IDL.Null,
+    'Testnet4' : IDL.Null,
+    'Regtest' : IDL.Null,
+  });
+  const InitArgs = IDL.Record({
+    'icp_ledger_canister' : IDL.Opt(IDL.Principal),
+    'etch_canister' : IDL.Opt(IDL.Principal),
+    'network' : BitcoinNetwork,
+    'rune_indexer_canister' : IDL.Opt(IDL.Principal),
+    'orchestrator_canister' : IDL.Opt(IDL.Principal),
+    'siwb_canister' : IDL.Opt(IDL.Principal),
+  });
+  const CookieArgs = IDL.Variant({
+    'Upgrade' : IDL.Opt(UpgradeArgs),
+    'Init' : InitArgs,
+  });
+  const Result_2 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text });
+  const ClaimViolation = IDL.Variant({
+    'ChallengeMismatch' : IDL.Null,
+    'ChallengeMissing' : IDL.Null,
+    'RateLimited' : IDL.Nat64,
+  });
   const GameStatus = IDL.Variant({
+    'Paused' : IDL.Null,
     'WaitAddedLiquidity' : IDL.Null,
     'Playing' : IDL.Null,
     'Withdrawing' : IDL.Null,
+    'EtchingFailed' : IDL.Text,
+    'Cancelled' : IDL.Null,
     'Etching' : IDL.Null,
   });
   const RejectionCode = IDL.Variant({
@@ -16,38 +49,58 @@ export const idlFactory = ({ IDL }) => {
   });
   const ExchangeError = IDL.Variant({
     'InvalidSignPsbtArgs' : IDL.Text,
+    'ClaimRejected' : ClaimViolation,
+    'InvalidTokenSplit' : IDL.Text,
     'InvalidNumeric' : IDL.Null,
     'ParseUtxoRuneBalanceError' : IDL.Text,
     'Overflow' : IDL.Null,
+    'InvalidActionParams' : IDL.Text,
+    'GamePaused' : IDL.Text,
     'InvalidInput' : IDL.Null,
     'PoolAddressNotFound' : IDL.Null,
     'NatConvertError' : IDL.Nat,
+    'NotGameCreator' : IDL.Text,
     'PoolNotFound' : IDL.Text,
     'RuneNotFound' : IDL.Text,
+    'NoCreatorAllocation' : IDL.Text,
+    'InvalidRefundCoins' : IDL.Text,
     'CookieBalanceInsufficient' : IDL.Nat,
+    'SelfReferral' : IDL.Text,
+    'TooManyGames' : IDL.Nat64,
     'GameEnd' : IDL.Null,
     'GamerAlreadyExist' : IDL.Text,
     'DuplicateBlock' : IDL.Tuple(IDL.Nat32, IDL.Text),
     'PoolStateExpired' : IDL.Nat64,
+    'InvalidGameArgs' : IDL.Text,
     'GamerNotFound' : IDL.Text,
+    'GameNameTooLong' : IDL.Nat64,
     'GameStatusNotMatch' : IDL.Tuple(GameStatus, GameStatus),
     'GameNotEnd' : IDL.Null,
+    'CreatorWithdrawRepeatedly' : IDL.Text,
     'TooSmallFunds' : IDL.Null,
     'Unrecoverable' : IDL.Null,
     'LastStateNotFound' : IDL.Null,
     'InvalidRuneId' : IDL.Null,
+    'ReferrerNotFound' : IDL.Text,
+    'AlreadyReferred' : IDL.Text,
+    'CallerNotAddressOwner' : IDL.Text,
     'InvalidPool' : IDL.Null,
     'InvalidPsbt' : IDL.Text,
     'GameNotFound' : IDL.Text,
     'PoolAlreadyExists' : IDL.Null,
     'GamerCoolingDown' : IDL.Tuple(IDL.Text, IDL.Nat64),
     'InvalidTxid' : IDL.Text,
+    'GameNameAlreadyExist' : IDL.Text,
+    'GameNotRefundable' : IDL.Text,
     'InvalidLiquidity' : IDL.Null,
+    'FetchPrincipalError' : IDL.Text,
     'DepositRuneBalanceIncorrect' : IDL.Tuple(IDL.Text, IDL.Text),
     'EmptyPool' : IDL.Null,
     'RuneIndexerResultError' : IDL.Text,
+    'RegisterFeeTooSmall' : IDL.Tuple(IDL.Nat64, IDL.Nat64),
     'LpNotFound' : IDL.Null,
     'ChainKeyError' : IDL.Null,
+    'InvalidWithdrawCoins' : IDL.Text,
     'FetchRuneIndexerError' : IDL.Tuple(RejectionCode, IDL.Text),
     'CustomError' : IDL.Text,
     'InvalidState' : IDL.Text,
@@ -55,18 +108,50 @@ export const idlFactory = ({ IDL }) => {
     'InsufficientFunds' : IDL.Null,
     'GamerWithdrawRepeatedly' : IDL.Text,
     'RuneIdNotMatch' : IDL.Tuple(IDL.Text, IDL.Text),
+    'GamerRefundRepeatedly' : IDL.Text,
     'PoolAddressMismatch' : IDL.Record({
       'actual' : IDL.Text,
       'expected' : IDL.Text,
     }),
   });
   const Result = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : ExchangeError });
+  const Result_10 = IDL.Variant({
+    'Ok' : IDL.Vec(IDL.Tuple(IDL.Text, Result)),
+    'Err' : ExchangeError,
+  });
+  const RewardSchedule = IDL.Variant({
+    'LinearDecay' : IDL.Record({ 'min_amount' : IDL.Nat }),
+    'Streak' : IDL.Record({
+      'max_bonus_bps' : IDL.Nat16,
+      'window' : IDL.Nat64,
+      'bonus_bps_per_click' : IDL.Nat16,
+    }),
+    'Constant' : IDL.Null,
+    'Halving' : IDL.Record({ 'interval' : IDL.Nat64 }),
+  });
+  const AntiBotSettings = IDL.Record({
+    'max_claims_per_minute' : IDL.Opt(IDL.Nat32),
+    'require_challenge' : IDL.Bool,
+    'cooldown_jitter' : IDL.Nat64,
+  });
+  const UnclaimedCookiesPolicy = IDL.Variant({
+    'DistributeToGamers' : IDL.Null,
+    'AddToLiquidity' : IDL.Null,
+  });
   const CreateGameArgs = IDL.Record({
+    'reward_schedule' : IDL.Opt(RewardSchedule),
+    'player_share_bps' : IDL.Opt(IDL.Nat16),
     'rune_premine_amount' : IDL.Nat,
     'create_address' : IDL.Text,
+    'end_time' : IDL.Opt(IDL.Nat64),
+    'creator_share_bps' : IDL.Opt(IDL.Nat16),
     'claim_amount_per_click' : IDL.Nat,
     'game_name' : IDL.Text,
+    'anti_bot' : IDL.Opt(AntiBotSettings),
+    'unclaimed_cookies_policy' : IDL.Opt(UnclaimedCookiesPolicy),
+    'referral_bonus' : IDL.Opt(IDL.Nat),
     'claim_cooling_down' : IDL.Nat64,
+    'pool_shards' : IDL.Opt(IDL.Nat8),
     'gamer_register_fee' : IDL.Nat64,
   });
   const Result_1 = IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text });
@@ -122,38 +207,73 @@ export const idlFactory = ({ IDL }) => {
     'intention_index' : IDL.Nat32,
     'psbt_hex' : IDL.Text,
   });
-  const RuneInfo = IDL.Record({ 'rune_name' : IDL.Text, 'rune_id' : IDL.Text });
-  const Gamer = IDL.Record({
-    'is_withdrawn' : IDL.Bool,
-    'last_click_time' : IDL.Nat64,
-    'address' : IDL.Text,
-    'cookies' : IDL.Nat,
+  const Result_8 = IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : ExchangeError });
+  const CanisterConfig = IDL.Record({
+    'icp_ledger_canister' : IDL.Principal,
+    'etch_canister' : IDL.Principal,
+    'network' : BitcoinNetwork,
+    'rune_indexer_canister' : IDL.Principal,
+    'orchestrator_canister' : IDL.Principal,
+    'siwb_canister' : IDL.Principal,
+  });
+  const TxStatus = IDL.Variant({
+    'Confirmed' : IDL.Null,
+    'Final' : IDL.Null,
+    'Pending' : IDL.Null,
   });
+  const TxProgress = IDL.Record({
+    'status' : TxStatus,
+    'txid' : IDL.Text,
+    'finalized_at' : IDL.Opt(IDL.Nat64),
+  });
+  const RuneInfo = IDL.Record({ 'rune_name' : IDL.Text, 'rune_id' : IDL.Text });
   const Game = IDL.Record({
+    'reward_schedule' : RewardSchedule,
+    'fee_pool_addresses' : IDL.Vec(IDL.Text),
+    'etch_check_count' : IDL.Nat32,
     'creator' : IDL.Principal,
+    'player_share_bps' : IDL.Nat16,
     'claimed_cookies' : IDL.Nat,
+    'unclaimed_cookies_left' : IDL.Nat,
     'rune_premine_amount' : IDL.Nat,
+    'creator_withdraw_tx' : IDL.Opt(TxProgress),
+    'add_liquidity_tx' : IDL.Opt(TxProgress),
     'creator_address' : IDL.Text,
     'rune_info' : IDL.Opt(RuneInfo),
-    'gamers' : IDL.Vec(IDL.Tuple(IDL.Text, Gamer)),
+    'fee_shards_with_liquidity' : IDL.Vec(IDL.Text),
+    'claim_count' : IDL.Nat64,
+    'fee_shard_gamer_counts' : IDL.Vec(IDL.Nat64),
+    'end_time' : IDL.Opt(IDL.Nat64),
+    'creator_share_bps' : IDL.Nat16,
     'claim_amount_per_click' : IDL.Nat,
     'game_id' : IDL.Text,
     'game_status' : GameStatus,
+    'unclaimed_cookies' : IDL.Nat,
     'game_name' : IDL.Text,
     'etch_rune_commit_tx' : IDL.Text,
+    'anti_bot' : AntiBotSettings,
+    'cookies_left' : IDL.Nat,
+    'unclaimed_cookies_policy' : UnclaimedCookiesPolicy,
+    'last_active_time' : IDL.Nat64,
+    'referral_bonus' : IDL.Nat,
     'pool_address' : IDL.Opt(IDL.Text),
     'claim_cooling_down' : IDL.Nat64,
+    'next_etch_check_time' : IDL.Nat64,
+    'gamer_count' : IDL.Nat64,
+    'etch_error' : IDL.Opt(IDL.Text),
+    'pool_shards' : IDL.Nat8,
+    'is_creator_allocation_withdrawn' : IDL.Bool,
+    'unclaimed_cookies_to_liquidity' : IDL.Nat,
     'gamer_register_fee' : IDL.Nat64,
   });
-  const ExchangeState = IDL.Record({
-    'txid_game_map' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
-    'games' : IDL.Vec(IDL.Tuple(IDL.Text, Game)),
-  });
   const UserAction = IDL.Variant({
     'Withdraw' : IDL.Tuple(IDL.Text, IDL.Text),
     'AddLiquidity' : IDL.Null,
     'Init' : IDL.Null,
+    'Refund' : IDL.Tuple(IDL.Text, IDL.Text),
+    'BatchWithdraw' : IDL.Tuple(IDL.Text, IDL.Vec(IDL.Text)),
     'Register' : IDL.Tuple(IDL.Text, IDL.Text),
+    'CreatorWithdraw' : IDL.Text,
   });
   const CookiePoolState = IDL.Record({
     'txid' : IDL.Text,
@@ -172,6 +292,50 @@ export const idlFactory = ({ IDL }) => {
     'pool_state' : IDL.Opt(CookiePoolState),
     'pool_metadata' : IDL.Opt(Metadata),
   });
+  const Gamer = IDL.Record({
+    'is_withdrawn' : IDL.Bool,
+    'streak' : IDL.Nat32,
+    'register_tx' : IDL.Opt(TxProgress),
+    'last_click_time' : IDL.Nat64,
+    'pool_shard' : IDL.Nat8,
+    'refund_tx' : IDL.Opt(TxProgress),
+    'withdraw_tx' : IDL.Opt(TxProgress),
+    'address' : IDL.Text,
+    'cookies' : IDL.Nat,
+    'unclaimed_cookies_bonus' : IDL.Nat,
+    'is_refunded' : IDL.Bool,
+  });
+  const GameAndGamer = IDL.Record({
+    'withdrawable_cookies' : IDL.Opt(IDL.Nat),
+    'claimed_cookies' : IDL.Nat,
+    'cookie_amount_per_claim' : IDL.Nat,
+    'is_end' : IDL.Bool,
+    'game_id' : IDL.Text,
+    'game_status' : GameStatus,
+    'gamer' : IDL.Opt(Gamer),
+    'game_name' : IDL.Text,
+    'next_claim_time' : IDL.Opt(IDL.Nat64),
+    'claim_cooling_down' : IDL.Nat64,
+    'gamer_register_fee' : IDL.Nat64,
+  });
+  const Result_5 = IDL.Variant({ 'Ok' : GameAndGamer, 'Err' : IDL.Text });
+  const GamerStats = IDL.Record({
+    'total_cookies' : IDL.Nat,
+    'address' : IDL.Text,
+    'total_withdrawn' : IDL.Nat,
+  });
+  const LeaderboardEntry = IDL.Record({
+    'address' : IDL.Text,
+    'cookies' : IDL.Nat,
+  });
+  const Result_7 = IDL.Variant({
+    'Ok' : IDL.Vec(LeaderboardEntry),
+    'Err' : IDL.Text,
+  });
+  const Result_6 = IDL.Variant({
+    'Ok' : IDL.Vec(GameAndGamer),
+    'Err' : IDL.Text,
+  });
   const GetPoolInfoArgs = IDL.Record({ 'pool_address' : IDL.Text });
   const PoolInfo = IDL.Record({
     'key' : IDL.Text,
@@ -185,47 +349,136 @@ export const idlFactory = ({ IDL }) => {
     'utxos' : IDL.Vec(Utxo),
   });
   const PoolBasic = IDL.Record({ 'name' : IDL.Text, 'address' : IDL.Text });
+  const Referral = IDL.Record({
+    'referrer' : IDL.Text,
+    'bonus' : IDL.Nat,
+    'referee' : IDL.Text,
+  });
+  const Result_9 = IDL.Variant({ 'Ok' : IDL.Vec(Referral), 'Err' : IDL.Text });
+  const Result_4 = IDL.Variant({ 'Ok' : IDL.Vec(Gamer), 'Err' : IDL.Text });
+  const GameSort = IDL.Variant({
+    'MostClaimed' : IDL.Null,
+    'Oldest' : IDL.Null,
+    'MostGamers' : IDL.Null,
+    'Newest' : IDL.Null,
+  });
+  const ListGamesArgs = IDL.Record({
+    'creator_filter' : IDL.Opt(IDL.Principal),
+    'status_filter' : IDL.Opt(GameStatus),
+    'sort' : IDL.Opt(GameSort),
+    'offset' : IDL.Nat64,
+    'limit' : IDL.Nat64,
+  });
+  const GameSummary = IDL.Record({
+    'creator' : IDL.Principal,
+    'claimed_cookies' : IDL.Nat,
+    'creator_address' : IDL.Text,
+    'rune_info' : IDL.Opt(RuneInfo),
+    'end_time' : IDL.Opt(IDL.Nat64),
+    'claim_amount_per_click' : IDL.Nat,
+    'claimable_amount' : IDL.Nat,
+    'game_id' : IDL.Text,
+    'game_status' : GameStatus,
+    'game_name' : IDL.Text,
+    'pool_address' : IDL.Opt(IDL.Text),
+    'claim_cooling_down' : IDL.Nat64,
+    'gamer_count' : IDL.Nat64,
+    'gamer_register_fee' : IDL.Nat64,
+  });
   const NewBlockInfo = IDL.Record({
     'block_hash' : IDL.Text,
     'confirmed_txids' : IDL.Vec(IDL.Text),
     'block_timestamp' : IDL.Nat64,
     'block_height' : IDL.Nat32,
   });
-  const Result_2 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text });
   const AddLiquidityInfo = IDL.Record({
     'btc_amount_for_add_liquidity' : IDL.Nat64,
     'rune_amount_for_add_liquidity' : IDL.Nat,
   });
+  const Result_3 = IDL.Variant({ 'Ok' : AddLiquidityInfo, 'Err' : IDL.Text });
   const RollbackTxArgs = IDL.Record({
     'txid' : IDL.Text,
     'reason_code' : IDL.Text,
   });
   return IDL.Service({
-    'claim' : IDL.Func([IDL.Text], [Result], []),
+    'cancel_game' : IDL.Func([IDL.Text], [Result_2], []),
+    'claim' : IDL.Func([IDL.Text, IDL.Opt(IDL.Nat64)], [Result], []),
+    'claim_many' : IDL.Func([IDL.Vec(IDL.Text)], [Result_10], []),
     'create_game' : IDL.Func([CreateGameArgs], [Result_1], []),
     'etch' : IDL.Func([EtchingArgs], [Result_1], []),
     'etch_rune' : IDL.Func([IDL.Text, IDL.Text], [Result_1], []),
     'execute_tx' : IDL.Func([ExecuteTxArgs], [Result_1], []),
     'finalize_etch' : IDL.Func([IDL.Text], [Result_1], []),
     'game_address' : IDL.Func([IDL.Text], [Result_1], []),
-    'get_exchange_state' : IDL.Func([], [ExchangeState], ['query']),
+    'get_claim_challenge' : IDL.Func([IDL.Text], [Result_8], ['query']),
+    'get_config' : IDL.Func([], [CanisterConfig], ['query']),
     'get_game_info' : IDL.Func([IDL.Text], [IDL.Opt(GameAndPool)], ['query']),
     'get_game_pool_address' : IDL.Func([IDL.Text], [IDL.Text], []),
+    'get_gamer_info' : IDL.Func([IDL.Text, IDL.Text], [Result_5], ['query']),
     'get_games_info' : IDL.Func([], [IDL.Vec(GameAndPool)], ['query']),
+    'get_global_leaderboard' : IDL.Func(
+        [IDL.Nat64],
+        [IDL.Vec(GamerStats)],
+        ['query'],
+      ),
+    'get_leaderboard' : IDL.Func([IDL.Text, IDL.Nat64], [Result_7], ['query']),
+    'get_my_games' : IDL.Func([], [Result_6], ['query']),
     'get_pool_info' : IDL.Func(
         [GetPoolInfoArgs],
         [IDL.Opt(PoolInfo)],
         ['query'],
       ),
     'get_pool_list' : IDL.Func([], [IDL.Vec(PoolBasic)], ['query']),
+    'get_referrals' : IDL.Func(
+        [IDL.Text, IDL.Nat64, IDL.Nat64],
+        [Result_9],
+        ['query'],
+      ),
+    'list_gamers' : IDL.Func(
+        [IDL.Text, IDL.Nat64, IDL.Nat64],
+        [Result_4],
+        ['query'],
+      ),
+    'list_games' : IDL.Func([ListGamesArgs], [IDL.Vec(GameSummary)], ['query']),
     'new_block' : IDL.Func([NewBlockInfo], [Result_2], []),
+    'pause_game' : IDL.Func([IDL.Text], [Result_2], []),
     'query_add_liquidity_info' : IDL.Func(
-        [IDL.Text],
-        [AddLiquidityInfo],
+        [IDL.Text, IDL.Opt(IDL.Nat8)],
+        [Result_3],
         ['query'],
       ),
     'query_etching_list' : IDL.Func([IDL.Text], [IDL.Vec(IDL.Text)], ['query']),
+    'resume_game' : IDL.Func([IDL.Text], [Result_2], []),
+    'retry_etch_rune' : IDL.Func([IDL.Text, IDL.Text], [Result_1], []),
     'rollback_tx' : IDL.Func([RollbackTxArgs], [Result_2], []),
+    'route_register_pool' : IDL.Func([IDL.Text], [Result_1], ['query']),
+    'update_config' : IDL.Func([UpgradeArgs], [Result_2], []),
+  });
+};
+export const init = ({ IDL }) => {
+  const UpgradeArgs = IDL.Record({
+    'icp_ledger_canister' : IDL.Opt(IDL.Principal),
+    'etch_canister' : IDL.Opt(IDL.Principal),
+    'rune_indexer_canister' : IDL.Opt(IDL.Principal),
+    'orchestrator_canister' : IDL.Opt(IDL.Principal),
+    'siwb_canister' : IDL.Opt(IDL.Principal),
+  });
+  const BitcoinNetwork = IDL.Variant({
+    'Mainnet' : IDL.Null,
+    'Testnet4' : IDL.Null,
+    'Regtest' : IDL.Null,
+  });
+  const InitArgs = IDL.Record({
+    'icp_ledger_canister' : IDL.Opt(IDL.Principal),
+    'etch_canister' : IDL.Opt(IDL.Principal),
+    'network' : BitcoinNetwork,
+    'rune_indexer_canister' : IDL.Opt(IDL.Principal),
+    'orchestrator_canister' : IDL.Opt(IDL.Principal),
+    'siwb_canister' : IDL.Opt(IDL.Principal),
+  });
+  const CookieArgs = IDL.Variant({
+    'Upgrade' : IDL.Opt(UpgradeArgs),
+    'Init' : InitArgs,
   });
+  return [CookieArgs];
 };
-export const init = ({ IDL }) => { return []; };
@@ -1,6 +1,6 @@
 import { useQuery } from "@tanstack/react-query";
 import { cookieActor } from "../canister/cookie/actor";
-import type { Game, GameAndPool } from "../canister/cookie/service.did";
+import type { Game, GameAndGamer, GameAndPool } from "../canister/cookie/service.did";
 import { etchActor } from "canister/etching/actor";
 
 export function useGames() {
@@ -30,6 +30,23 @@ export function useGame( gameId: string) {
 
 }
 
+export function useGamer(gameId: string, address: string | undefined) {
+
+    return useQuery<GameAndGamer>({
+        queryKey: ["gamer", gameId, address],
+        queryFn: async () => {
+            const res = await cookieActor.get_gamer_info(gameId, address!);
+            if ("Err" in res) {
+                throw new Error(res.Err);
+            }
+            return res.Ok;
+        },
+        enabled: !!address,
+        refetchInterval: 60 * 1000, // Refetch every 60 seconds
+    })
+
+}
+
 export function useEtchingRequest(commitTxid: string | undefined) {
     return useQuery({
         queryKey: ["etching-result", commitTxid],
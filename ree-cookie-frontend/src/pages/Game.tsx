@@ -32,7 +32,7 @@ import { PoolBasic } from "canister/rich-swap/service.did";
 import { connectWalletModalOpenAtom } from "components/ConnectDialog";
 import { Register } from "components/Register";
 import { sign } from "crypto";
-import { useEtchingRequest, useGame } from "hooks/use-pool";
+import { useEtchingRequest, useGame, useGamer } from "hooks/use-pool";
 import { useWalletBtcUtxos } from "hooks/use-utxos";
 import { useSiwbIdentity } from "ic-siwb-lasereyes-connector";
 import { useSetAtom } from "jotai";
@@ -46,10 +46,14 @@ import { withdrawTx } from "utils/tx-helper/withdraw";
 const { Timer } = Statistic;
 
 export const stateStepIndex = (game_status: GameStatus) => {
-  if ("Etching" in game_status) {
+  if ("Etching" in game_status || "EtchingFailed" in game_status) {
     return 0;
   }
-  if ("Playing" in game_status) {
+  if (
+    "Playing" in game_status ||
+    "Paused" in game_status ||
+    "Cancelled" in game_status
+  ) {
     return 1;
   }
   // if ("Etching" in game_status) {
@@ -151,12 +155,13 @@ function Playing({
   // )
   const setConnectWalletModalOpen = useSetAtom(connectWalletModalOpenAtom);
   const { identityAddress, identity } = useSiwbIdentity();
-  const currentGamer = useMemo(() => {
-    return game.gamers.find(
-      (gamer) => gamer[0] === identityAddress
-    )?.[1];
-  }, [game.gamers, identityAddress]);
-  const isGameEnd = stateStepIndex(game.game_status) > 1;
+  const { data: gamerInfo, isLoading: isLoadingGamer } = useGamer(
+    game.game_id,
+    identityAddress
+  );
+  const currentGamer = gamerInfo?.gamer[0];
+  const isGameEnd =
+    stateStepIndex(game.game_status) > 1 || "Cancelled" in game.game_status;
 
   return (
     <div>
@@ -166,7 +171,7 @@ function Playing({
         {isGameEnd ? (
           <div>Game Is End</div>
         ) : identityAddress ? (
-          !false ? (
+          !isLoadingGamer ? (
             currentGamer ? (
               <Claim
                 game={game}
@@ -383,10 +388,16 @@ function WaitAddedLiquidity({
     const f = async () => {
       let pool_list = await swapActor.get_pool_list();
       let pool = pool_list.find((e) => e.name === rune_name);
+      // the liquidity is added from shard 0, the pool holding the runes
       let addLiquidityInfo = await cookieActor.query_add_liquidity_info(
-        game.game_id
+        game.game_id,
+        []
       );
-      setAddLiquidityInfo(addLiquidityInfo);
+      if ("Ok" in addLiquidityInfo) {
+        setAddLiquidityInfo(addLiquidityInfo.Ok);
+      } else {
+        console.error(addLiquidityInfo.Err);
+      }
       setGameSwapPool(pool);
     };
 
@@ -528,9 +539,11 @@ function Withdrawing({
 
   const rune_id = game.rune_info[0]!.rune_id;
 
-  const currentGamer = useMemo(() => {
-    return game.gamers.find((gamer) => gamer[0] === address)?.[1];
-  }, [game.gamers, address]);
+  const { data: gamerInfo } = useGamer(game.game_id, address);
+  const currentGamer = gamerInfo?.gamer[0];
+  // the cookies plus the share of the unclaimed cookies
+  const withdrawableCookies =
+    gamerInfo?.withdrawable_cookies[0] ?? currentGamer?.cookies;
 
   const withdrawCookies = async () => {
     let recommendedFeeRate = await ocActor
@@ -553,7 +566,7 @@ function Withdrawing({
       feeRate: Number(recommendedFeeRate.toString()),
       signPsbt: signPsbt,
       cookiePoolNonce: pool_state.nonce,
-      withdrawAmount: BigInt(currentGamer!.cookies),
+      withdrawAmount: BigInt(withdrawableCookies!),
     });
   };
 
@@ -583,7 +596,7 @@ function Withdrawing({
                 });
             }}
           >
-            Withdraw {currentGamer!.cookies} game rune token
+            Withdraw {withdrawableCookies!.toString()} game rune token
           </Button>
         )
       ) : (
@@ -620,7 +633,13 @@ function Claim({
 
   const clickClaim = async () => {
     console.log("try to claim cookies");
-    let res = await cookieActorWithIdentity(identity!).claim(game.game_id);
+    let actor = cookieActorWithIdentity(identity!);
+    // games without a claim challenge ignore the nonce
+    let challenge = await actor.get_claim_challenge(game.game_id);
+    let res = await actor.claim(
+      game.game_id,
+      "Ok" in challenge ? [challenge.Ok] : []
+    );
     console.log({ res });
     if ("Ok" in res) {
       messageApi.open({